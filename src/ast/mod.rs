@@ -7,10 +7,121 @@ pub type ExpRef = usize;
 
 pub type Program = Vec<Box<Expression>>;
 
+// int, *char, [int; 4], (int, string), func(int): int, Pair<int>
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int,
+    Char,
+    String,
+    Void,
+    // Anything the type checker couldn't figure out, fits every type.
+    Any,
+    Pointer(Box<Type>),
+    Array(Box<Type>, usize),
+    Tuple(Vec<Type>),
+    Function { params: Vec<Type>, return_type: Box<Type> },
+    // A struct, enum or type parameter.
+    Named(String),
+    Generic { name: String, args: Vec<Type> },
+}
+
+impl Type {
+    pub fn function(params: Vec<Type>, return_type: Option<Type>) -> Self {
+        return Type::Function {
+            params,
+            return_type: Box::new(return_type.unwrap_or(Type::Void)),
+        };
+    }
+
+    // Pair<int>, or just Pair without type arguments.
+    pub fn generic(name: &str, args: Vec<Type>) -> Self {
+        if args.is_empty() {
+            return Type::Named(name.to_string());
+        }
+        return Type::Generic { name: name.to_string(), args };
+    }
+
+    // Rebuilds the type with `f` applied to every type it is made of.
+    pub fn map(&self, mut f: impl FnMut(&Type) -> Type) -> Type {
+        match self {
+            Type::Pointer(t) => Type::Pointer(Box::new(f(t))),
+            Type::Array(t, len) => Type::Array(Box::new(f(t)), *len),
+            Type::Tuple(elements) => Type::Tuple(elements.iter().map(&mut f).collect()),
+            Type::Function { params, return_type } => Type::Function {
+                params: params.iter().map(&mut f).collect(),
+                return_type: Box::new(f(return_type)),
+            },
+            Type::Generic { name, args } => Type::Generic {
+                name: name.clone(),
+                args: args.iter().map(&mut f).collect(),
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+fn join(types: &[Type]) -> String {
+    return types.iter().map(|t| t.to_string()).collect::<Vec<String>>().join(", ");
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Char => write!(f, "char"),
+            Type::String => write!(f, "string"),
+            Type::Void => write!(f, "void"),
+            Type::Any => write!(f, "any"),
+            Type::Pointer(t) => write!(f, "*{}", t),
+            Type::Array(t, len) => write!(f, "[{}; {}]", t, len),
+            Type::Tuple(elements) => write!(f, "({})", join(elements)),
+            Type::Function { params, return_type } if **return_type == Type::Void => {
+                write!(f, "func({})", join(params))
+            }
+            Type::Function { params, return_type } => write!(f, "func({}): {}", join(params), return_type),
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Generic { name, args } => write!(f, "{}<{}>", name, join(args)),
+        }
+    }
+}
+
+// What an identifier refers to, filled in by the resolver.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Declaration {
+    Unresolved,
+    // A variable or parameter, by the span of its declaration.
+    Local(Span),
+    // A function, extern function, struct or enum.
+    Global(Span),
+    // print, println, len, int and char
+    Builtin,
+}
+
 #[derive(Serialize, Clone)]
 pub struct Parameter {
     pub name: String, 
-    pub param_type: String,
+    pub param_type: Type,
+    pub span: Span,
+}
+
+// @allow(unused_variables) in front of a function.
+#[derive(Serialize, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+    pub span: Span,
+}
+
+#[derive(Serialize, Clone)]
+pub enum Pattern {
+    Variant { enum_name: String, variant: String },
+    Wildcard,
+}
+
+#[derive(Serialize, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Vec<Box<Statement>>,
 }
 
 #[derive(Serialize, Clone)]
@@ -22,18 +133,60 @@ pub enum Statement {
     },
     FuncStatement {
         name: String,
-        return_type: Option<String>,
+        public: bool,
+        span: Span,
+        attributes: Vec<Attribute>,
+        type_params: Vec<String>,
+        return_type: Option<Type>,
         call_inputs: Vec<Parameter>,
         body: Vec<Box<Statement>>,
     },
     VarStatement {
         name: String,
+        span: Span,
         value: Option<ExpRef>,
-        var_type: String
+        // None until the type checker inferred it from the value.
+        var_type: Option<Type>
     },
-    ReturnStatement {
+    DestructureStatement {
+        names: Vec<String>,
+        span: Span,
         value: ExpRef
     },
+    // return; has no value
+    ReturnStatement {
+        span: Span,
+        value: Option<ExpRef>
+    },
+    EnumStatement {
+        name: String,
+        public: bool,
+        span: Span,
+        variants: Vec<String>,
+    },
+    StructStatement {
+        name: String,
+        public: bool,
+        span: Span,
+        type_params: Vec<String>,
+        fields: Vec<Parameter>,
+    },
+    MatchStatement {
+        value: ExpRef,
+        arms: Vec<MatchArm>,
+    },
+    // extern func printf(fmt: string, ...): int;
+    ExternStatement {
+        name: String,
+        span: Span,
+        call_inputs: Vec<Parameter>,
+        return_type: Option<Type>,
+        variadic: bool,
+    },
+    // import "util"; and import util.math; both become the path [util, math]
+    ImportStatement {
+        path: Vec<String>,
+    },
 
     ExpressionStatement(ExpRef)
 }
@@ -46,9 +199,21 @@ pub enum Expression {
     InfixExpression { left: ExpRef, op: TokenType, right: ExpRef },
     PrefixExpression { op: TokenType, right: ExpRef },
     AssignExpression { left: ExpRef, right: ExpRef },
+    FieldAccess { left: ExpRef, field: String },
+    Lambda {
+        call_inputs: Vec<Parameter>,
+        return_type: Option<Type>,
+        body: Vec<Box<Statement>>,
+    },
+    Index { left: ExpRef, index: ExpRef },
+    Slice { left: ExpRef, start: Option<ExpRef>, end: Option<ExpRef> },
+
+    Tuple(Vec<ExpRef>),
 
     Integer(i64),
+    Char(u8),
     String(String), 
-    Identifier { value: String , ident_type: String},
+    Identifier { value: String , ident_type: Type, decl: Declaration },
+    GenericInstance { value: String, type_args: Vec<Type> },
 
 }
@@ -0,0 +1,719 @@
+use std::fmt;
+
+use crate::ir::{self, BinOp, Callee, Data, Inst, Operand, Terminator, VReg};
+
+mod peephole;
+mod regalloc;
+mod runtime;
+
+use regalloc::{allocate, Location};
+
+pub use peephole::Peephole;
+
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum OpCodeTypes {
+    Add,
+    Sub,
+    Imul,
+    Mov,
+    Push,
+    Pop,
+    Xor,
+    And,
+    Call,
+    Func(String),
+    Global,
+    Extern,
+    Leave,
+    Ret,
+    Cmp,
+    Lea,
+    Movzx,
+    Setl,
+    Setg,
+    Setle,
+    Setge,
+    Sete,
+    Setne,
+    Cmovl,
+    Cmovg,
+    Je,
+    Jne,
+    Jmp,
+    Ud2,
+    Db,
+    Dq,
+}
+impl fmt::Display for OpCodeTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpCodeTypes::Func(s) => {
+                write!(f, "{}:", s)
+            }
+            OpCodeTypes::Extern => {
+                write!(f, "extern")
+            }
+            OpCodeTypes::Global=> {
+                write!(f, "global")
+            }
+            _ => {
+                write!(f, "{}", format!("\t{:?}", self).to_uppercase())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    opcode: OpCodeTypes,
+    operands: Vec<String>
+}
+// The 16 general purpose registers and their 32, 16 and 8 bit parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registers {
+    RAX, RBX, RCX, RDX, RSI, RDI, RBP, RSP, R8, R9, R10, R11, R12, R13, R14, R15,
+    EAX, EBX, ECX, EDX, ESI, EDI, EBP, ESP, R8D, R9D, R10D, R11D, R12D, R13D, R14D, R15D,
+    AX, BX, CX, DX, SI, DI, BP, SP, R8W, R9W, R10W, R11W, R12W, R13W, R14W, R15W,
+    AL, BL, CL, DL, SIL, DIL, BPL, SPL, R8B, R9B, R10B, R11B, R12B, R13B, R14B, R15B,
+}
+
+use Registers::*;
+
+// One row per register, from 64 down to 8 bits.
+const GPRS: [[Registers; 4]; 16] = [
+    [RAX, EAX, AX, AL],
+    [RBX, EBX, BX, BL],
+    [RCX, ECX, CX, CL],
+    [RDX, EDX, DX, DL],
+    [RSI, ESI, SI, SIL],
+    [RDI, EDI, DI, DIL],
+    [RBP, EBP, BP, BPL],
+    [RSP, ESP, SP, SPL],
+    [R8, R8D, R8W, R8B],
+    [R9, R9D, R9W, R9B],
+    [R10, R10D, R10W, R10B],
+    [R11, R11D, R11W, R11B],
+    [R12, R12D, R12W, R12B],
+    [R13, R13D, R13W, R13B],
+    [R14, R14D, R14W, R14B],
+    [R15, R15D, R15W, R15B],
+];
+
+impl Registers {
+    pub fn to_string(&self) -> String {
+        return format!("{:?}", self).to_uppercase()
+    }
+
+    // The part of the register with this many bits, RAX.sub(8) is AL.
+    pub fn sub(self, bits: u32) -> Registers {
+        let column = match bits {
+            64 => 0,
+            32 => 1,
+            16 => 2,
+            8 => 3,
+            _ => panic!("There is no {} bit register", bits),
+        };
+        let row = GPRS.iter().find(|row| row.contains(&self)).unwrap();
+        return row[column];
+    }
+
+    pub fn parse(name: &str) -> Option<Registers> {
+        return GPRS.iter().flatten().find(|r| r.to_string() == name.to_uppercase()).copied();
+    }
+}
+
+// Extern functions are called with the System V calling convention: the first
+// six arguments go into registers, the rest is passed on a 16 byte aligned stack.
+const ARG_REGISTERS: [Registers; 6] = [
+    Registers::RDI,
+    Registers::RSI,
+    Registers::RDX,
+    Registers::RCX,
+    Registers::R8,
+    Registers::R9,
+];
+
+// Generates NASM for an IR module. Virtual registers live in machine registers
+// picked by the register allocator or in spill slots in the frame, and RAX,
+// RBX, RDX and R10 are scratch registers.
+//
+// Compiled functions take their arguments on the stack, pushed right to left,
+// and return one slot in RAX and two in RAX:RDX. Bigger results are written
+// to memory reserved by the caller, its address is passed as hidden first
+// argument and returned in RAX.
+pub struct Backend {
+    output: Vec<Instruction>,
+    data_section: Vec<Instruction>,
+    externs: Vec<String>,
+    // Extern functions taking a variable number of arguments.
+    variadic: Vec<String>,
+    runtime: Vec<ir::Runtime>,
+    cur_cond_idx: i64,
+    // Labels of the blocks of the current function.
+    labels: Vec<String>,
+    // Where the registers of the current function live.
+    locations: Vec<Location>,
+    // Callee saved registers the current function uses and their frame offsets.
+    saved: Vec<(Registers, u64)>,
+    // Frame offset of the hidden return pointer of the current function.
+    ret_ptr: Option<u64>,
+    is_main: bool,
+    peephole: Peephole,
+}
+
+impl Backend {
+    pub fn new(peephole: Peephole) -> Self {
+        return Self {
+            output: Vec::new(),
+            data_section: Vec::new(),
+            externs: Vec::new(),
+            variadic: Vec::new(),
+            runtime: Vec::new(),
+            cur_cond_idx: 0,
+            labels: Vec::new(),
+            locations: Vec::new(),
+            saved: Vec::new(),
+            ret_ptr: None,
+            is_main: false,
+            peephole,
+        };
+    }
+
+    fn new_instruction(&mut self, opcode: OpCodeTypes, operands: Vec<String>) {
+        let instruction = Instruction{
+            opcode,
+            operands
+        };
+        self.output.push(instruction);
+    }
+    fn alloc(&mut self, bytes: u32) {
+        self.new_instruction(OpCodeTypes::Sub, vec![Registers::RSP.to_string(), format!("{}", bytes)]);
+    }
+
+    fn push_reg(&mut self, reg: Registers) {
+        self.new_instruction(OpCodeTypes::Push, vec![reg.to_string()]);
+    }
+
+    fn pop(&mut self, reg: Registers) {
+        self.new_instruction(OpCodeTypes::Pop, vec![reg.to_string()]);
+    }
+
+    fn register_op(&mut self, opcode: OpCodeTypes, reg1: Registers, reg2: Registers) {
+        self.new_instruction(opcode, vec![reg1.to_string(), reg2.to_string()]);
+    }
+
+    fn new_label(&mut self) -> String {
+        let label = format!(".A{}", self.cur_cond_idx);
+        self.cur_cond_idx += 1;
+        return label;
+    }
+
+    fn slot(&self, reg: VReg) -> String {
+        return self.locations[reg].to_string();
+    }
+
+    fn memory(width: &str, reg: Registers, offset: i64) -> String {
+        match offset {
+            0 => format!("{} [{}]", width, reg.to_string()),
+            o if o < 0 => format!("{} [{} - {}]", width, reg.to_string(), -o),
+            o => format!("{} [{} + {}]", width, reg.to_string(), o),
+        }
+    }
+
+    fn load(&mut self, operand: &Operand, reg: Registers) {
+        let value = match operand {
+            Operand::Reg(r) => self.slot(*r),
+            Operand::Imm(i) => format!("{}", i),
+            Operand::Label(l) => l.clone(),
+        };
+        self.new_instruction(OpCodeTypes::Mov, vec![reg.to_string(), value]);
+    }
+
+    fn store(&mut self, dest: VReg, reg: Registers) {
+        self.new_instruction(OpCodeTypes::Mov, vec![self.slot(dest), reg.to_string()]);
+    }
+
+    pub(super) fn add_extern(&mut self, name: &str) {
+        if !self.externs.iter().any(|e| e == name) {
+            self.externs.push(name.to_string());
+        }
+    }
+
+    fn setup_stackfram(&mut self) {
+        self.push_reg(Registers::RBP);
+        self.register_op(OpCodeTypes::Mov, Registers::RBP, Registers::RSP);
+    }
+
+    fn compile_function(&mut self, function: &ir::Function) {
+        if function.exported {
+            self.new_instruction(OpCodeTypes::Global, vec![function.name.clone()]);
+        }
+        self.new_instruction(OpCodeTypes::Func(function.name.clone()), vec![]);
+        self.setup_stackfram();
+        let allocation = allocate(function);
+        self.locations = allocation.locations;
+        let mut slots = allocation.spill_slots;
+        self.ret_ptr = None;
+        if function.returns.len() > 2 {
+            slots += 1;
+            self.ret_ptr = Some(slots * 8);
+        }
+        self.saved = Vec::new();
+        for reg in allocation.saved {
+            slots += 1;
+            self.saved.push((reg, slots * 8));
+        }
+        self.alloc(std::cmp::max((slots * 8).div_ceil(16) * 16, 16) as u32);
+        self.is_main = function.name == "main";
+        for (reg, offset) in self.saved.clone() {
+            self.new_instruction(OpCodeTypes::Mov, vec![format!("QWORD [rbp-{}]", offset), reg.to_string()]);
+        }
+
+        let mut arg_offset = 16;
+        if let Some(ptr) = self.ret_ptr {
+            self.new_instruction(OpCodeTypes::Mov, vec![Registers::RAX.to_string(), format!("QWORD [RBP + {}]", arg_offset)]);
+            self.new_instruction(OpCodeTypes::Mov, vec![format!("QWORD [rbp-{}]", ptr), Registers::RAX.to_string()]);
+            arg_offset += 8;
+        }
+        for param in &function.params {
+            self.new_instruction(OpCodeTypes::Mov, vec![Registers::RAX.to_string(), format!("QWORD [RBP + {}]", arg_offset)]);
+            self.store(*param, Registers::RAX);
+            arg_offset += 8;
+        }
+        // Closures get their closure object in R10.
+        if let Some(env) = function.env {
+            self.store(env, Registers::R10);
+        }
+
+        self.labels = function.blocks.iter().map(|_| self.new_label()).collect();
+        for (id, block) in function.blocks.iter().enumerate() {
+            self.new_instruction(OpCodeTypes::Func(self.labels[id].clone()), vec![]);
+            for inst in &block.insts {
+                self.compile_inst(inst);
+            }
+            self.compile_terminator(&block.term);
+        }
+    }
+
+    fn compile_inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Copy { dest, src } => {
+                self.load(src, Registers::RAX);
+                self.store(*dest, Registers::RAX);
+            }
+            Inst::Binary { dest, op, left, right } => {
+                self.load(left, Registers::RAX);
+                self.load(right, Registers::RBX);
+                let opcode = match op {
+                    BinOp::Add => OpCodeTypes::Add,
+                    BinOp::Sub => OpCodeTypes::Sub,
+                    BinOp::Mul => OpCodeTypes::Imul,
+                    BinOp::And => OpCodeTypes::And,
+                    BinOp::Xor => OpCodeTypes::Xor,
+                    _ => {
+                        self.comp(*op);
+                        self.store(*dest, Registers::RAX);
+                        return;
+                    }
+                };
+                self.register_op(opcode, Registers::RAX, Registers::RBX);
+                self.store(*dest, Registers::RAX);
+            }
+            Inst::Load { dest, addr, offset, byte } => {
+                self.load(addr, Registers::RAX);
+                if *byte {
+                    self.new_instruction(OpCodeTypes::Movzx, vec![
+                        Registers::RAX.to_string(),
+                        Self::memory("BYTE", Registers::RAX, *offset),
+                    ]);
+                } else {
+                    self.new_instruction(OpCodeTypes::Mov, vec![
+                        Registers::RAX.to_string(),
+                        Self::memory("QWORD", Registers::RAX, *offset),
+                    ]);
+                }
+                self.store(*dest, Registers::RAX);
+            }
+            Inst::Store { addr, offset, value } => {
+                self.load(addr, Registers::RAX);
+                self.load(value, Registers::RBX);
+                self.new_instruction(OpCodeTypes::Mov, vec![
+                    Self::memory("QWORD", Registers::RAX, *offset),
+                    Registers::RBX.to_string(),
+                ]);
+            }
+            Inst::Call { dests, callee: Callee::Extern(name), args } => {
+                self.compile_extern_call(name, args, dests.first().copied());
+            }
+            Inst::Call { dests, callee: Callee::Runtime(helper), args } => {
+                if !self.runtime.contains(helper) {
+                    self.runtime.push(*helper);
+                }
+                for arg in args.iter().rev() {
+                    self.load(arg, Registers::RAX);
+                    self.push_reg(Registers::RAX);
+                }
+                self.new_instruction(OpCodeTypes::Call, vec![helper.to_string()]);
+                self.new_instruction(OpCodeTypes::Add, vec![
+                    Registers::RSP.to_string(),
+                    format!("{}", args.len() * 8),
+                ]);
+                self.store(dests[0], Registers::RAX);
+            }
+            Inst::Call { dests, callee, args } => {
+                self.compile_call(dests, callee, args);
+            }
+            Inst::Phi { .. } => {
+                panic!("Phi left in {}, the IR has to be taken out of SSA form first", inst);
+            }
+        }
+    }
+
+    // COMPARE RAX op RBX
+    fn comp(&mut self, op: BinOp) {
+        self.register_op(OpCodeTypes::Cmp, Registers::RAX, Registers::RBX);
+        let opcode = match op {
+            BinOp::Lt => OpCodeTypes::Setl,
+            BinOp::Gt => OpCodeTypes::Setg,
+            BinOp::Le => OpCodeTypes::Setle,
+            BinOp::Ge => OpCodeTypes::Setge,
+            BinOp::Eq => OpCodeTypes::Sete,
+            BinOp::Ne => OpCodeTypes::Setne,
+            _ => unreachable!(),
+        };
+        self.new_instruction(opcode, vec![Registers::RAX.sub(8).to_string()]);
+        self.register_op(OpCodeTypes::Movzx, Registers::RAX, Registers::RAX.sub(8));
+    }
+
+    fn compile_call(&mut self, dests: &[VReg], callee: &Callee, args: &[Operand]) {
+        // Results bigger than RAX:RDX are written to space reserved by the caller.
+        let return_size = dests.len();
+        if return_size > 2 {
+            self.alloc(return_size as u32 * 8);
+        }
+        for arg in args.iter().rev() {
+            self.load(arg, Registers::RAX);
+            self.push_reg(Registers::RAX);
+        }
+        let mut arg_slots = args.len();
+        if return_size > 2 {
+            self.new_instruction(OpCodeTypes::Lea, vec![
+                Registers::RBX.to_string(),
+                format!("[RSP + {}]", arg_slots * 8),
+            ]);
+            self.push_reg(Registers::RBX);
+            arg_slots += 1;
+        }
+        match callee {
+            Callee::Direct(name) => self.new_instruction(OpCodeTypes::Call, vec![name.clone()]),
+            Callee::Indirect(closure) => {
+                self.load(closure, Registers::R10);
+                self.new_instruction(OpCodeTypes::Call, vec![String::from("QWORD [R10]")]);
+            }
+            _ => unreachable!(),
+        }
+        if arg_slots > 0 {
+            self.new_instruction(OpCodeTypes::Add, vec![
+                Registers::RSP.to_string(),
+                format!("{}", arg_slots * 8),
+            ]);
+        }
+        match return_size {
+            0 => {}
+            1 => self.store(dests[0], Registers::RAX),
+            2 => {
+                self.store(dests[0], Registers::RAX);
+                self.store(dests[1], Registers::RDX);
+            }
+            // The result is on top of the stack now, element 0 first.
+            _ => {
+                for dest in dests {
+                    self.pop(Registers::RBX);
+                    self.store(*dest, Registers::RBX);
+                }
+            }
+        }
+    }
+
+    fn compile_extern_call(&mut self, name: &String, args: &[Operand], dest: Option<VReg>) {
+        self.add_extern(name);
+        let in_registers = std::cmp::min(args.len(), ARG_REGISTERS.len());
+        let on_stack = args.len() - in_registers;
+        // Arguments can live in argument registers, so they are all read
+        // before any argument register is set.
+        for arg in args.iter().rev() {
+            self.load(arg, Registers::RAX);
+            self.push_reg(Registers::RAX);
+        }
+        for reg in &ARG_REGISTERS[..in_registers] {
+            self.pop(*reg);
+        }
+
+        // How deep the stack is isn't known here, so it is aligned at runtime.
+        // RBX keeps the old stack pointer.
+        self.register_op(OpCodeTypes::Mov, Registers::RBX, Registers::RSP);
+        self.new_instruction(OpCodeTypes::And, vec![Registers::RSP.to_string(), String::from("-16")]);
+        if on_stack > 0 {
+            self.alloc(on_stack.div_ceil(2) as u32 * 16);
+            for i in 0..on_stack {
+                self.new_instruction(OpCodeTypes::Mov, vec![
+                    Registers::RAX.to_string(),
+                    format!("QWORD [RBX + {}]", i * 8),
+                ]);
+                self.new_instruction(OpCodeTypes::Mov, vec![
+                    format!("QWORD [RSP + {}]", i * 8),
+                    Registers::RAX.to_string(),
+                ]);
+            }
+        }
+        if self.variadic.contains(name) {
+            // AL holds the number of vector registers used, there are no floats.
+            self.register_op(OpCodeTypes::Xor, Registers::EAX, Registers::EAX);
+        }
+        self.new_instruction(OpCodeTypes::Call, vec![name.clone()]);
+        self.new_instruction(OpCodeTypes::Lea, vec![
+            Registers::RSP.to_string(),
+            format!("[RBX + {}]", on_stack * 8),
+        ]);
+        if let Some(dest) = dest {
+            self.store(dest, Registers::RAX);
+        }
+    }
+
+    // Small results go into RAX:RDX, bigger ones are copied to the
+    // caller provided memory and its address is returned in RAX.
+    fn compile_terminator(&mut self, term: &Terminator) {
+        match term {
+            Terminator::Jump(b) => {
+                self.new_instruction(OpCodeTypes::Jmp, vec![self.labels[*b].clone()]);
+            }
+            Terminator::Branch { cond, then, otherwise } => {
+                self.load(cond, Registers::RAX);
+                self.new_instruction(OpCodeTypes::Cmp, vec![Registers::RAX.to_string(), String::from("0")]);
+                self.new_instruction(OpCodeTypes::Je, vec![self.labels[*otherwise].clone()]);
+                self.new_instruction(OpCodeTypes::Jmp, vec![self.labels[*then].clone()]);
+            }
+            Terminator::Return(values) => {
+                match values.len() {
+                    0 => {
+                        if self.is_main {
+                            self.register_op(OpCodeTypes::Xor, Registers::EAX, Registers::EAX);
+                        }
+                    }
+                    1 => self.load(&values[0], Registers::RAX),
+                    2 => {
+                        self.load(&values[0], Registers::RAX);
+                        self.load(&values[1], Registers::RDX);
+                    }
+                    _ => {
+                        let ptr = self.ret_ptr.unwrap();
+                        self.new_instruction(OpCodeTypes::Mov, vec![Registers::RBX.to_string(), format!("QWORD [rbp-{}]", ptr)]);
+                        for (i, value) in values.iter().enumerate() {
+                            self.load(value, Registers::RAX);
+                            self.new_instruction(OpCodeTypes::Mov, vec![
+                                Self::memory("QWORD", Registers::RBX, i as i64 * 8),
+                                Registers::RAX.to_string(),
+                            ]);
+                        }
+                        self.register_op(OpCodeTypes::Mov, Registers::RAX, Registers::RBX);
+                    }
+                }
+                for (reg, offset) in self.saved.clone() {
+                    self.new_instruction(OpCodeTypes::Mov, vec![reg.to_string(), format!("QWORD [rbp-{}]", offset)]);
+                }
+                self.new_instruction(OpCodeTypes::Leave, vec![]);
+                self.new_instruction(OpCodeTypes::Ret, vec![]);
+            }
+            Terminator::Unreachable => {
+                self.new_instruction(OpCodeTypes::Ud2, vec![]);
+            }
+        }
+    }
+
+    fn db_operands(s: &str) -> Vec<String> {
+        let mut operands = Vec::new();
+        let mut run = String::new();
+        for b in s.bytes() {
+            if (b' '..=b'~').contains(&b) && b != b'"' {
+                run.push(b as char);
+                continue;
+            }
+            if !run.is_empty() {
+                operands.push(format!("\"{}\"", run));
+                run.clear();
+            }
+            operands.push(format!("{}", b));
+        }
+        if !run.is_empty() {
+            operands.push(format!("\"{}\"", run));
+        }
+        operands.push(String::from("0"));
+        return operands;
+    }
+
+    fn compile_data(&mut self, data: &Data) {
+        match data {
+            Data::String { label, value } => {
+                self.data_section.push(Instruction {
+                    opcode: OpCodeTypes::Dq,
+                    operands: vec![format!("{}", value.len())]
+                });
+                self.data_section.push(Instruction {
+                    opcode: OpCodeTypes::Func(label.clone()),
+                    operands: vec![]
+                });
+                self.data_section.push(Instruction {
+                    opcode: OpCodeTypes::Db,
+                    operands: Self::db_operands(value),
+                });
+            }
+            Data::Closure { label, function } => {
+                self.data_section.push(Instruction {
+                    opcode: OpCodeTypes::Func(label.clone()),
+                    operands: vec![],
+                });
+                self.data_section.push(Instruction {
+                    opcode: OpCodeTypes::Dq,
+                    operands: vec![function.clone()],
+                });
+            }
+        }
+    }
+
+    pub fn compile(&mut self, module: &ir::Module) {
+        for e in &module.externs {
+            self.add_extern(&e.name);
+            if e.variadic {
+                self.variadic.push(e.name.clone());
+            }
+        }
+        for name in &module.imports {
+            self.add_extern(name);
+        }
+        for data in &module.data {
+            self.compile_data(data);
+        }
+        for function in &module.functions {
+            self.compile_function(function);
+        }
+        self.emit_runtime();
+        self.output = self.peephole.run(std::mem::take(&mut self.output));
+        let mut header: Vec<Instruction> = Vec::new();
+        if !module.separate {
+            header.push(Instruction {
+                opcode: OpCodeTypes::Global,
+                operands: vec![String::from("main")],
+            });
+        }
+        header.extend(self.externs
+            .iter()
+            .map(|e| Instruction {
+                opcode: OpCodeTypes::Extern,
+                operands: vec![e.clone()],
+            }));
+        self.output.splice(0..0, header);
+    }
+}
+
+
+impl std::fmt::Display for Instruction{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = self.operands.join(", ");
+        write!(f, "{} {}", format!("{}", self.opcode), s)
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "section .data\n")?;
+        if self.data_section.len() > 0 {
+            write!(f, "{}", self.data_section.iter()
+                .map(|inst| format!("{}", inst))
+                .collect::<Vec<String>>()
+                .join("\n")
+            )?;
+            write!(f, "\n")?;
+        }
+        write!(f, "\nsection .text\n")?;
+        write!(f, "{}", self.output.iter()
+            .map(|inst| format!("{}", inst))
+            .collect::<Vec<String>>()
+            .join("\n")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::parser::Parser;
+    use crate::semantic::{check_types, resolve_names};
+    use crate::tokenizer::Tokenizer;
+
+    fn assemble(source: &str) -> String {
+        let mut parser = Parser::new(Tokenizer::new(source.trim_start().to_string()));
+        let mut stmts = parser.parse_program();
+        let (mut program, spans) = (parser.get_program(), parser.get_spans());
+        assert!(resolve_names(&stmts.iter().collect::<Vec<_>>(), &mut program, &spans).is_empty());
+        let (errors, types) = check_types(stmts.iter_mut().collect(), &mut program, &spans);
+        assert!(errors.is_empty());
+        let module = Compiler::new(stmts, program, types).compile();
+        let mut backend = Backend::new(Peephole::new());
+        backend.compile(&module);
+        return backend.to_string();
+    }
+
+    // NASM puts a label starting with a dot under the last label without one,
+    // in definitions and in uses alike.
+    fn scoped(name: &str, parent: &str) -> String {
+        if name.starts_with('.') {
+            return format!("{}{}", parent, name);
+        }
+        return name.to_string();
+    }
+
+    #[test]
+    fn string_labels_resolve_from_every_function() {
+        let asm = assemble(r#"
+func greet(name: string): string {
+  return "hello " + name;
+}
+
+func main(): int {
+  println("%s", greet("you"));
+  var f: func(int): int = func(x: int): int {
+    println("lambda");
+    return x;
+  };
+  return f(0);
+}
+"#);
+        let mut parent = String::new();
+        let mut defined = HashSet::new();
+        let mut used = Vec::new();
+        for line in asm.lines().map(|l| l.trim()).filter(|l| !l.starts_with("section")) {
+            if let Some(label) = line.strip_suffix(':') {
+                if !label.starts_with('.') {
+                    parent = label.to_string();
+                }
+                defined.insert(scoped(label, &parent));
+                continue;
+            }
+            let operands = line.split_once(' ').map(|(_, o)| o).unwrap_or("");
+            for word in operands.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.')) {
+                if word.starts_with('.') || word.starts_with("__str") {
+                    used.push(scoped(word, &parent));
+                }
+            }
+        }
+        assert!(used.iter().any(|l| l.starts_with("__str")));
+        for label in used {
+            assert!(defined.contains(&label), "{} is not defined in\n{}", label, asm);
+        }
+    }
+}
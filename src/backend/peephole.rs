@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+
+use super::*;
+
+// Local rewrites of the generated instructions. Each rule looks at a window of
+// two instructions and can be turned off on its own with --no-peephole.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Rule {
+    // push x; pop r        =>  mov r, x
+    PushPop,
+    // mov m, r; mov r, m   =>  mov m, r
+    RedundantLoad,
+    // mov RAX, x; push RAX  =>  push x, when RAX is set again right after
+    PushOperand,
+    // jmp .L; .L:          =>  .L:
+    JumpToNext,
+    // mov r, 0             =>  xor r, r, when no one reads the flags
+    ZeroXor,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 5] = [
+        Rule::PushPop,
+        Rule::RedundantLoad,
+        Rule::PushOperand,
+        Rule::JumpToNext,
+        Rule::ZeroXor,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::PushPop => "push-pop",
+            Rule::RedundantLoad => "redundant-load",
+            Rule::PushOperand => "push-operand",
+            Rule::JumpToNext => "jump-to-next",
+            Rule::ZeroXor => "zero-xor",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Peephole {
+    enabled: HashSet<Rule>,
+}
+
+impl Peephole {
+    pub fn new() -> Self {
+        return Peephole { enabled: Rule::ALL.into_iter().collect() };
+    }
+
+    // Turns off the rule with this name, `all` turns off every rule.
+    pub fn disable(&mut self, name: &str) -> bool {
+        if name == "all" {
+            self.enabled.clear();
+            return true;
+        }
+        match Rule::ALL.iter().find(|r| r.name() == name) {
+            Some(rule) => {
+                self.enabled.remove(rule);
+                return true;
+            }
+            None => return false,
+        }
+    }
+
+    // Applies the rules until none of them matches anymore.
+    pub fn run(&self, mut code: Vec<Instruction>) -> Vec<Instruction> {
+        loop {
+            let mut changed = false;
+            let mut out = Vec::with_capacity(code.len());
+            let mut i = 0;
+            while i < code.len() {
+                match self.rewrite(&code, i) {
+                    Some((replacement, consumed)) => {
+                        out.extend(replacement);
+                        i += consumed;
+                        changed = true;
+                    }
+                    None => {
+                        out.push(code[i].clone());
+                        i += 1;
+                    }
+                }
+            }
+            code = out;
+            if !changed {
+                return code;
+            }
+        }
+    }
+
+    // The instructions replacing the ones starting at `i` and how many of them
+    // are replaced, None if no rule matches.
+    fn rewrite(&self, code: &[Instruction], i: usize) -> Option<(Vec<Instruction>, usize)> {
+        let a = &code[i];
+        let b = code.get(i + 1);
+        let on = |rule| self.enabled.contains(&rule);
+        let rax = Registers::RAX.to_string();
+
+        if let (OpCodeTypes::Push, Some(Instruction { opcode: OpCodeTypes::Pop, operands: popped })) = (&a.opcode, b) {
+            if on(Rule::PushPop) {
+                if a.operands[0] == popped[0] {
+                    return Some((Vec::new(), 2));
+                }
+                return Some((vec![instruction(OpCodeTypes::Mov, &popped[0], &a.operands[0])], 2));
+            }
+        }
+        if let (OpCodeTypes::Mov, Some(Instruction { opcode: OpCodeTypes::Mov, operands: next })) = (&a.opcode, b) {
+            // mov RAX, [RAX]; mov [RAX], RAX writes somewhere else.
+            let reloaded = next[0] == a.operands[1] && next[1] == a.operands[0] && !a.operands[1].contains(&a.operands[0]);
+            if on(Rule::RedundantLoad) && reloaded {
+                return Some((vec![a.clone()], 2));
+            }
+        }
+        if let (OpCodeTypes::Mov, Some(Instruction { opcode: OpCodeTypes::Push, operands: pushed })) = (&a.opcode, b) {
+            let source = &a.operands[1];
+            if on(Rule::PushOperand) && a.operands[0] == rax && pushed[0] == rax && pushable(source) && overwritten(code.get(i + 2), &rax) {
+                return Some((vec![Instruction { opcode: OpCodeTypes::Push, operands: vec![source.clone()] }], 2));
+            }
+        }
+        if let (OpCodeTypes::Jmp, Some(Instruction { opcode: OpCodeTypes::Func(label), .. })) = (&a.opcode, b) {
+            if on(Rule::JumpToNext) && a.operands[0] == *label {
+                return Some((Vec::new(), 1));
+            }
+        }
+        let zero = a.opcode == OpCodeTypes::Mov && a.operands[1] == "0" && !a.operands[0].contains('[');
+        if on(Rule::ZeroXor) && zero && !flags_read(&code[i + 1..]) {
+            return Some((vec![instruction(OpCodeTypes::Xor, &a.operands[0], &a.operands[0])], 1));
+        }
+        return None;
+    }
+}
+
+fn instruction(opcode: OpCodeTypes, dest: &str, src: &str) -> Instruction {
+    return Instruction { opcode, operands: vec![dest.to_string(), src.to_string()] };
+}
+
+// push takes a 64 bit register, memory or a sign extended 32 bit immediate.
+fn pushable(operand: &str) -> bool {
+    if operand.contains('[') || Registers::parse(operand).is_some_and(|r| r.sub(64) == r) {
+        return true;
+    }
+    if let Ok(i) = operand.parse::<i64>() {
+        return i32::try_from(i).is_ok();
+    }
+    return false;
+}
+
+// Whether the instruction sets `reg` without reading it.
+fn overwritten(next: Option<&Instruction>, reg: &str) -> bool {
+    match next {
+        Some(Instruction { opcode: OpCodeTypes::Mov | OpCodeTypes::Lea | OpCodeTypes::Movzx, operands }) => {
+            operands[0] == reg && !operands[1].contains(reg)
+        }
+        _ => false,
+    }
+}
+
+// Whether the flags are read before the next instruction setting them. Flags
+// are never used across labels, jumps or calls.
+fn flags_read(code: &[Instruction]) -> bool {
+    for inst in code {
+        match inst.opcode {
+            OpCodeTypes::Je | OpCodeTypes::Jne | OpCodeTypes::Setl | OpCodeTypes::Setg |
+            OpCodeTypes::Setle | OpCodeTypes::Setge | OpCodeTypes::Sete | OpCodeTypes::Setne |
+            OpCodeTypes::Cmovl | OpCodeTypes::Cmovg => return true,
+            OpCodeTypes::Cmp | OpCodeTypes::Add | OpCodeTypes::Sub | OpCodeTypes::Imul |
+            OpCodeTypes::And | OpCodeTypes::Xor | OpCodeTypes::Func(_) | OpCodeTypes::Jmp |
+            OpCodeTypes::Call | OpCodeTypes::Ret => return false,
+            _ => {}
+        }
+    }
+    return false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inst(opcode: OpCodeTypes, operands: &[&str]) -> Instruction {
+        return Instruction { opcode, operands: operands.iter().map(|o| o.to_string()).collect() };
+    }
+
+    fn label(name: &str) -> Instruction {
+        return inst(OpCodeTypes::Func(name.to_string()), &[]);
+    }
+
+    // A peephole optimizer with only this rule turned on.
+    fn only(rule: Rule) -> Peephole {
+        let mut peephole = Peephole::new();
+        for other in Rule::ALL.iter().filter(|r| **r != rule) {
+            peephole.disable(other.name());
+        }
+        return peephole;
+    }
+
+    // Code the rule rewrites and what it becomes.
+    fn fires(rule: Rule) -> (Vec<Instruction>, Vec<Instruction>) {
+        match rule {
+            Rule::PushPop => (
+                vec![inst(OpCodeTypes::Push, &["RCX"]), inst(OpCodeTypes::Pop, &["RSI"])],
+                vec![inst(OpCodeTypes::Mov, &["RSI", "RCX"])],
+            ),
+            Rule::RedundantLoad => (
+                vec![inst(OpCodeTypes::Mov, &["QWORD [rbp-8]", "RAX"]), inst(OpCodeTypes::Mov, &["RAX", "QWORD [rbp-8]"])],
+                vec![inst(OpCodeTypes::Mov, &["QWORD [rbp-8]", "RAX"])],
+            ),
+            Rule::PushOperand => (
+                vec![
+                    inst(OpCodeTypes::Mov, &["RAX", "QWORD [rbp-8]"]),
+                    inst(OpCodeTypes::Push, &["RAX"]),
+                    inst(OpCodeTypes::Mov, &["RAX", "5"]),
+                ],
+                vec![inst(OpCodeTypes::Push, &["QWORD [rbp-8]"]), inst(OpCodeTypes::Mov, &["RAX", "5"])],
+            ),
+            Rule::JumpToNext => (
+                vec![inst(OpCodeTypes::Jmp, &[".A1"]), label(".A1")],
+                vec![label(".A1")],
+            ),
+            Rule::ZeroXor => (
+                vec![inst(OpCodeTypes::Mov, &["RAX", "0"]), inst(OpCodeTypes::Ret, &[])],
+                vec![inst(OpCodeTypes::Xor, &["RAX", "RAX"]), inst(OpCodeTypes::Ret, &[])],
+            ),
+        }
+    }
+
+    #[test]
+    fn every_rule_fires() {
+        for rule in Rule::ALL {
+            let (code, expected) = fires(rule);
+            assert_eq!(only(rule).run(code), expected, "{}", rule.name());
+        }
+    }
+
+    #[test]
+    fn push_pop_of_the_same_register_is_removed() {
+        let code = vec![inst(OpCodeTypes::Push, &["RCX"]), inst(OpCodeTypes::Pop, &["RCX"])];
+        assert_eq!(only(Rule::PushPop).run(code), Vec::new());
+    }
+
+    #[test]
+    fn redundant_load_keeps_reload_after_overwrite() {
+        let code = vec![
+            inst(OpCodeTypes::Mov, &["QWORD [rbp-8]", "RAX"]),
+            inst(OpCodeTypes::Mov, &["RAX", "RBX"]),
+            inst(OpCodeTypes::Mov, &["RAX", "QWORD [rbp-8]"]),
+        ];
+        assert_eq!(only(Rule::RedundantLoad).run(code.clone()), code);
+    }
+
+    #[test]
+    fn redundant_load_keeps_store_through_loaded_register() {
+        let code = vec![inst(OpCodeTypes::Mov, &["RAX", "QWORD [RAX]"]), inst(OpCodeTypes::Mov, &["QWORD [RAX]", "RAX"])];
+        assert_eq!(only(Rule::RedundantLoad).run(code.clone()), code);
+    }
+
+    #[test]
+    fn push_operand_keeps_rax_that_is_still_read() {
+        let code = vec![
+            inst(OpCodeTypes::Mov, &["RAX", "QWORD [rbp-8]"]),
+            inst(OpCodeTypes::Push, &["RAX"]),
+            inst(OpCodeTypes::Add, &["RBX", "RAX"]),
+        ];
+        assert_eq!(only(Rule::PushOperand).run(code.clone()), code);
+    }
+
+    #[test]
+    fn push_operand_keeps_wide_immediates() {
+        let code = vec![
+            inst(OpCodeTypes::Mov, &["RAX", "9223372036854775807"]),
+            inst(OpCodeTypes::Push, &["RAX"]),
+            inst(OpCodeTypes::Mov, &["RAX", "5"]),
+        ];
+        assert_eq!(only(Rule::PushOperand).run(code.clone()), code);
+    }
+
+    #[test]
+    fn jump_to_other_label_stays() {
+        let code = vec![inst(OpCodeTypes::Jmp, &[".A2"]), label(".A1")];
+        assert_eq!(only(Rule::JumpToNext).run(code.clone()), code);
+    }
+
+    #[test]
+    fn zero_xor_keeps_mov_when_flags_are_read() {
+        let code = vec![
+            inst(OpCodeTypes::Cmp, &["RAX", "RBX"]),
+            inst(OpCodeTypes::Mov, &["RAX", "0"]),
+            inst(OpCodeTypes::Sete, &["AL"]),
+        ];
+        assert_eq!(only(Rule::ZeroXor).run(code.clone()), code);
+    }
+
+    #[test]
+    fn zero_xor_keeps_stores_to_memory() {
+        let code = vec![inst(OpCodeTypes::Mov, &["QWORD [rbp-8]", "0"]), inst(OpCodeTypes::Ret, &[])];
+        assert_eq!(only(Rule::ZeroXor).run(code.clone()), code);
+    }
+
+    #[test]
+    fn disable_turns_off_only_that_rule() {
+        for disabled in Rule::ALL {
+            let mut peephole = Peephole::new();
+            assert!(peephole.disable(disabled.name()));
+            for rule in Rule::ALL {
+                let (code, expected) = fires(rule);
+                let expected = if rule == disabled { code.clone() } else { expected };
+                assert_eq!(peephole.run(code), expected, "{} with {} disabled", rule.name(), disabled.name());
+            }
+        }
+    }
+
+    #[test]
+    fn disable_all_and_unknown_rules() {
+        let mut peephole = Peephole::new();
+        assert!(!peephole.disable("no-such-rule"));
+        assert!(peephole.disable("all"));
+        for rule in Rule::ALL {
+            let (code, _) = fires(rule);
+            assert_eq!(peephole.run(code.clone()), code);
+        }
+    }
+}
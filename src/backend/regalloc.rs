@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ir::{Function, Inst, Operand, VReg};
+use super::Registers;
+
+// Registers handed out by the allocator. RAX, RBX, RDX and R10 are left to the
+// instruction selection as scratch, return and closure registers.
+const CALLER_SAVED: [Registers; 6] = [
+    Registers::RCX,
+    Registers::RSI,
+    Registers::RDI,
+    Registers::R8,
+    Registers::R9,
+    Registers::R11,
+];
+const CALLEE_SAVED: [Registers; 4] = [
+    Registers::R12,
+    Registers::R13,
+    Registers::R14,
+    Registers::R15,
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Location {
+    Register(Registers),
+    // A spilled register lives at [rbp-offset].
+    Stack(u64),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Register(r) => write!(f, "{}", r.to_string()),
+            Location::Stack(offset) => write!(f, "QWORD [rbp-{}]", offset),
+        }
+    }
+}
+
+pub struct Allocation {
+    // Where every virtual register lives, indexed by VReg.
+    pub locations: Vec<Location>,
+    // Stack slots used for spilled registers, from [rbp-8] down.
+    pub spill_slots: u64,
+    // Callee saved registers that have to be restored before returning.
+    pub saved: Vec<Registers>,
+}
+
+// The positions a register is live at, from its first definition to its
+// last use. Holes in between are not tracked.
+struct Interval {
+    reg: VReg,
+    start: usize,
+    end: usize,
+    // Whether a call happens while the register is live, its value has to
+    // survive the call in a callee saved register or on the stack.
+    crosses_call: bool,
+}
+
+// Linear scan register allocation. Instructions are numbered in block order,
+// params are defined at position 0. Intervals are handed registers in the
+// order they start, when none is free the interval ending last is spilled.
+pub fn allocate(function: &Function) -> Allocation {
+    let intervals = intervals(function);
+    let mut locations: Vec<Option<Location>> = vec![None; function.regs.len()];
+    let mut active: Vec<(usize, Registers)> = Vec::new();
+    let mut free: Vec<Registers> = CALLER_SAVED.iter().chain(CALLEE_SAVED.iter()).copied().collect();
+    let mut spill_slots = 0;
+    let mut spill = |locations: &mut Vec<Option<Location>>, reg: VReg| {
+        spill_slots += 1;
+        locations[reg] = Some(Location::Stack(spill_slots * 8));
+    };
+
+    for (i, interval) in intervals.iter().enumerate() {
+        active.retain(|(a, r)| {
+            if intervals[*a].end <= interval.start {
+                free.push(*r);
+                return false;
+            }
+            return true;
+        });
+        let fits = |r: &Registers| !interval.crosses_call || CALLEE_SAVED.contains(r);
+        // Caller saved registers first, they don't have to be saved.
+        let choice = CALLER_SAVED
+            .iter()
+            .chain(CALLEE_SAVED.iter())
+            .find(|r| free.contains(r) && fits(r))
+            .copied();
+        if let Some(r) = choice {
+            free.retain(|f| *f != r);
+            locations[interval.reg] = Some(Location::Register(r));
+            active.push((i, r));
+            continue;
+        }
+        // Take the register of the active interval that ends last if it ends after this one.
+        let victim = active
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, r))| fits(r))
+            .max_by_key(|(_, (a, _))| intervals[*a].end)
+            .map(|(pos, (a, r))| (pos, *a, *r));
+        match victim {
+            Some((pos, a, r)) if intervals[a].end > interval.end => {
+                spill(&mut locations, intervals[a].reg);
+                locations[interval.reg] = Some(Location::Register(r));
+                active[pos] = (i, r);
+            }
+            _ => spill(&mut locations, interval.reg),
+        }
+    }
+
+    let mut saved: Vec<Registers> = Vec::new();
+    let locations: Vec<Location> = locations
+        .into_iter()
+        // Registers that appear in no instruction are never accessed.
+        .map(|l| l.unwrap_or(Location::Register(Registers::R11)))
+        .collect();
+    for l in &locations {
+        if let Location::Register(r) = l {
+            if CALLEE_SAVED.contains(r) && !saved.contains(r) {
+                saved.push(*r);
+            }
+        }
+    }
+    saved.sort_by_key(|r| CALLEE_SAVED.iter().position(|c| c == r));
+    return Allocation { locations, spill_slots, saved };
+}
+
+fn uses(inst: &Inst) -> Vec<VReg> {
+    return inst.operands()
+        .into_iter()
+        .filter_map(|o| match o {
+            Operand::Reg(r) => Some(*r),
+            _ => None,
+        })
+        .collect();
+}
+
+fn intervals(function: &Function) -> Vec<Interval> {
+    let n = function.blocks.len();
+    // Registers live at the start of each block, iterated to a fixpoint.
+    let mut live_in: Vec<HashSet<VReg>> = vec![HashSet::new(); n];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in (0..n).rev() {
+            let block = &function.blocks[b];
+            let mut live: HashSet<VReg> = HashSet::new();
+            for s in block.term.successors() {
+                live.extend(live_in[s].iter().copied());
+            }
+            for op in block.term.operands() {
+                if let Operand::Reg(r) = op {
+                    live.insert(*r);
+                }
+            }
+            for inst in block.insts.iter().rev() {
+                for d in inst.defs() {
+                    live.remove(&d);
+                }
+                live.extend(uses(inst));
+            }
+            if live != live_in[b] {
+                live_in[b] = live;
+                changed = true;
+            }
+        }
+    }
+
+    let mut range: Vec<Option<(usize, usize)>> = vec![None; function.regs.len()];
+    let mut touch = |reg: VReg, pos: usize| {
+        range[reg] = Some(match range[reg] {
+            Some((s, e)) => (s.min(pos), e.max(pos)),
+            None => (pos, pos),
+        });
+    };
+    for reg in function.params.iter().chain(function.env.iter()) {
+        touch(*reg, 0);
+    }
+    let mut calls = Vec::new();
+    let mut pos = 1;
+    for block in &function.blocks {
+        let start = pos;
+        let end = start + block.insts.len();
+        let mut live: HashSet<VReg> = HashSet::new();
+        for s in block.term.successors() {
+            live.extend(live_in[s].iter().copied());
+        }
+        for reg in &live {
+            touch(*reg, end);
+        }
+        for op in block.term.operands() {
+            if let Operand::Reg(r) = op {
+                touch(*r, end);
+                live.insert(*r);
+            }
+        }
+        for (i, inst) in block.insts.iter().enumerate().rev() {
+            let at = start + i;
+            if matches!(inst, Inst::Call { .. }) {
+                calls.push(at);
+            }
+            for d in inst.defs() {
+                touch(d, at);
+                live.remove(&d);
+            }
+            for u in uses(inst) {
+                touch(u, at);
+                live.insert(u);
+            }
+        }
+        for reg in &live {
+            touch(*reg, start);
+        }
+        pos = end + 1;
+    }
+
+    let mut intervals: Vec<Interval> = range
+        .into_iter()
+        .enumerate()
+        .filter_map(|(reg, r)| r.map(|(start, end)| Interval {
+            reg,
+            start,
+            end,
+            crosses_call: calls.iter().any(|c| start < *c && *c < end),
+        }))
+        .collect();
+    intervals.sort_by_key(|i| i.start);
+    return intervals;
+}
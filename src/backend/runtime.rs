@@ -0,0 +1,129 @@
+use super::*;
+use crate::ir::Runtime;
+
+// Strings are pointers to NUL terminated bytes with the length stored
+// as a QWORD right in front of them, so they can still be handed to printf.
+//
+// The helpers below use the same calling convention as compiled functions:
+// arguments are pushed right to left and the result is returned in RAX.
+impl Backend {
+    fn emit(&mut self, opcode: OpCodeTypes, operands: &[&str]) {
+        self.new_instruction(opcode, operands.iter().map(|o| o.to_string()).collect());
+    }
+
+    pub(super) fn emit_runtime(&mut self) {
+        for helper in self.runtime.clone() {
+            self.new_instruction(OpCodeTypes::Func(helper.to_string()), vec![]);
+            match helper {
+                Runtime::StrConcat => self.str_concat(),
+                Runtime::StrEq => self.str_eq(),
+                Runtime::StrSlice => self.str_slice(),
+            }
+        }
+    }
+
+    // Sets up a frame with `locals` bytes and aligns the stack for libc calls.
+    fn runtime_prologue(&mut self, locals: u32) {
+        self.setup_stackfram();
+        self.alloc(locals);
+        self.emit(OpCodeTypes::And, &["RSP", "-16"]);
+    }
+
+    // Allocates a string with the length in [RBP-8] and leaves the pointer to its bytes in RAX.
+    fn alloc_string(&mut self) {
+        self.add_extern("malloc");
+        self.emit(OpCodeTypes::Mov, &["RDI", "QWORD [RBP-8]"]);
+        self.emit(OpCodeTypes::Add, &["RDI", "9"]);
+        self.emit(OpCodeTypes::Call, &["malloc"]);
+        self.emit(OpCodeTypes::Mov, &["RBX", "QWORD [RBP-8]"]);
+        self.emit(OpCodeTypes::Mov, &["QWORD [RAX]", "RBX"]);
+        self.emit(OpCodeTypes::Add, &["RAX", "8"]);
+        self.emit(OpCodeTypes::Mov, &["BYTE [RAX+RBX]", "0"]);
+    }
+
+    // __str_concat(left, right)
+    fn str_concat(&mut self) {
+        self.add_extern("memcpy");
+        self.runtime_prologue(16);
+        self.emit(OpCodeTypes::Mov, &["RAX", "QWORD [RBP+24]"]);
+        self.emit(OpCodeTypes::Mov, &["RBX", "QWORD [RBP+16]"]);
+        self.emit(OpCodeTypes::Mov, &["RDI", "QWORD [RAX-8]"]);
+        self.emit(OpCodeTypes::Add, &["RDI", "QWORD [RBX-8]"]);
+        self.emit(OpCodeTypes::Mov, &["QWORD [RBP-8]", "RDI"]);
+        self.alloc_string();
+        self.emit(OpCodeTypes::Mov, &["QWORD [RBP-16]", "RAX"]);
+
+        self.emit(OpCodeTypes::Mov, &["RDI", "RAX"]);
+        self.emit(OpCodeTypes::Mov, &["RSI", "QWORD [RBP+16]"]);
+        self.emit(OpCodeTypes::Mov, &["RDX", "QWORD [RSI-8]"]);
+        self.emit(OpCodeTypes::Call, &["memcpy"]);
+
+        self.emit(OpCodeTypes::Mov, &["RSI", "QWORD [RBP+16]"]);
+        self.emit(OpCodeTypes::Mov, &["RDI", "QWORD [RBP-16]"]);
+        self.emit(OpCodeTypes::Add, &["RDI", "QWORD [RSI-8]"]);
+        self.emit(OpCodeTypes::Mov, &["RSI", "QWORD [RBP+24]"]);
+        self.emit(OpCodeTypes::Mov, &["RDX", "QWORD [RSI-8]"]);
+        self.emit(OpCodeTypes::Call, &["memcpy"]);
+
+        self.emit(OpCodeTypes::Mov, &["RAX", "QWORD [RBP-16]"]);
+        self.emit(OpCodeTypes::Leave, &[]);
+        self.emit(OpCodeTypes::Ret, &[]);
+    }
+
+    // __str_eq(a, b) compares by length and content and returns 0 or 1.
+    fn str_eq(&mut self) {
+        self.add_extern("memcmp");
+        let differ = self.new_label();
+        self.runtime_prologue(0);
+        self.emit(OpCodeTypes::Mov, &["RDI", "QWORD [RBP+16]"]);
+        self.emit(OpCodeTypes::Mov, &["RSI", "QWORD [RBP+24]"]);
+        self.emit(OpCodeTypes::Mov, &["RDX", "QWORD [RDI-8]"]);
+        self.emit(OpCodeTypes::Cmp, &["RDX", "QWORD [RSI-8]"]);
+        self.emit(OpCodeTypes::Jne, &[&differ]);
+        self.emit(OpCodeTypes::Call, &["memcmp"]);
+        self.emit(OpCodeTypes::Cmp, &["EAX", "0"]);
+        self.emit(OpCodeTypes::Sete, &["AL"]);
+        self.emit(OpCodeTypes::Movzx, &["RAX", "AL"]);
+        self.emit(OpCodeTypes::Leave, &[]);
+        self.emit(OpCodeTypes::Ret, &[]);
+        self.new_instruction(OpCodeTypes::Func(differ), vec![]);
+        self.emit(OpCodeTypes::Xor, &["RAX", "RAX"]);
+        self.emit(OpCodeTypes::Leave, &[]);
+        self.emit(OpCodeTypes::Ret, &[]);
+    }
+
+    // __str_slice(s, start, end) copies s[start:end], a negative end means
+    // the end of the string and both bounds are clamped to the string.
+    fn str_slice(&mut self) {
+        self.add_extern("memcpy");
+        self.runtime_prologue(32);
+        self.emit(OpCodeTypes::Mov, &["RSI", "QWORD [RBP+16]"]);
+        self.emit(OpCodeTypes::Mov, &["RCX", "QWORD [RSI-8]"]);
+        self.emit(OpCodeTypes::Mov, &["RAX", "QWORD [RBP+24]"]);
+        self.emit(OpCodeTypes::Mov, &["RDX", "QWORD [RBP+32]"]);
+        self.emit(OpCodeTypes::Cmp, &["RDX", "0"]);
+        self.emit(OpCodeTypes::Cmovl, &["RDX", "RCX"]);
+        self.emit(OpCodeTypes::Cmp, &["RDX", "RCX"]);
+        self.emit(OpCodeTypes::Cmovg, &["RDX", "RCX"]);
+        self.emit(OpCodeTypes::Xor, &["RBX", "RBX"]);
+        self.emit(OpCodeTypes::Cmp, &["RAX", "0"]);
+        self.emit(OpCodeTypes::Cmovl, &["RAX", "RBX"]);
+        self.emit(OpCodeTypes::Cmp, &["RAX", "RDX"]);
+        self.emit(OpCodeTypes::Cmovg, &["RAX", "RDX"]);
+        self.emit(OpCodeTypes::Sub, &["RDX", "RAX"]);
+        self.emit(OpCodeTypes::Mov, &["QWORD [RBP-8]", "RDX"]);
+        self.emit(OpCodeTypes::Mov, &["QWORD [RBP-16]", "RAX"]);
+        self.alloc_string();
+        self.emit(OpCodeTypes::Mov, &["QWORD [RBP-24]", "RAX"]);
+
+        self.emit(OpCodeTypes::Mov, &["RDI", "RAX"]);
+        self.emit(OpCodeTypes::Mov, &["RSI", "QWORD [RBP+16]"]);
+        self.emit(OpCodeTypes::Add, &["RSI", "QWORD [RBP-16]"]);
+        self.emit(OpCodeTypes::Mov, &["RDX", "QWORD [RBP-8]"]);
+        self.emit(OpCodeTypes::Call, &["memcpy"]);
+
+        self.emit(OpCodeTypes::Mov, &["RAX", "QWORD [RBP-24]"]);
+        self.emit(OpCodeTypes::Leave, &[]);
+        self.emit(OpCodeTypes::Ret, &[]);
+    }
+}
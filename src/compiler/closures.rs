@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use super::*;
+
+// Every function value is a pointer to a closure object:
+//
+//     [code address, captured slot 0, captured slot 1, ...]
+//
+// Indirect calls pass the closure pointer in R10 (the static chain register
+// of the System V ABI) and call through its first QWORD. Named functions
+// used as values get a static closure object without captures.
+impl Compiler {
+    pub(super) fn static_closure(&mut self, name: &String) -> String {
+        if self.extern_functions.contains_key(name) {
+            panic!("The extern function {} can't be used as a value", name);
+        }
+        let label = format!("__closure_{}", name);
+        if !self.static_closures.contains(name) {
+            self.static_closures.push(name.clone());
+            self.module.data.push(Data::Closure { label: label.clone(), function: name.clone() });
+        }
+        return label;
+    }
+
+    pub(super) fn compile_lambda(&mut self, call_inputs: Vec<Parameter>, return_type: Option<Type>, body: Vec<Box<Statement>>) -> Vec<Operand> {
+        let captures = self.captures(&call_inputs, &body);
+        let label = format!("__lambda{}", self.cur_lambda_idx);
+        self.cur_lambda_idx += 1;
+
+        let function = self.compile_function(label.clone(), call_inputs, return_type, body, captures.clone());
+        self.lambdas.push(function);
+
+        let slots: usize = captures.iter().map(|(_, t)| self.size_of(t)).sum();
+        if !self.module.externs.iter().any(|e| e.name == "malloc") {
+            self.module.externs.push(ir::Extern { name: String::from("malloc"), variadic: false });
+        }
+        let closure = self.call(
+            Callee::Extern(String::from("malloc")),
+            vec![Operand::Imm(((slots + 1) * 8) as i64)],
+            vec![Ty::Ptr],
+        )[0].clone();
+        self.emit(Inst::Store { addr: closure.clone(), offset: 0, value: Operand::Label(label) });
+        let mut env_offset = 8;
+        for (name, _) in captures {
+            let regs = self.table.get(name).unwrap().regs.clone();
+            for reg in regs {
+                self.emit(Inst::Store { addr: closure.clone(), offset: env_offset, value: Operand::Reg(reg) });
+                env_offset += 8;
+            }
+        }
+        return vec![closure];
+    }
+
+    // Variables of the enclosing scopes that are used but not declared inside the lambda.
+    fn captures(&self, call_inputs: &Vec<Parameter>, body: &Vec<Box<Statement>>) -> Vec<(String, Type)> {
+        let mut used = Vec::new();
+        let mut scopes: Vec<HashSet<String>> = Vec::new();
+        self.collect_body(call_inputs, body, &mut used, &mut scopes);
+        let mut captures: Vec<(String, Type)> = Vec::new();
+        for name in used {
+            if captures.iter().any(|(n, _)| *n == name) {
+                continue;
+            }
+            if let Some(symbol) = self.table.get(name.clone()) {
+                captures.push((name, symbol.symb_type.clone()));
+            }
+        }
+        return captures;
+    }
+
+    // `scopes` holds the names declared in each block around the current
+    // statement, a name is only used from outside when no block declares it.
+    fn collect_body(&self, call_inputs: &Vec<Parameter>, body: &Vec<Box<Statement>>, used: &mut Vec<String>, scopes: &mut Vec<HashSet<String>>) {
+        scopes.push(call_inputs.iter().map(|p| p.name.clone()).collect());
+        self.collect_block(body, used, scopes);
+        scopes.pop();
+    }
+
+    fn collect_block(&self, body: &Vec<Box<Statement>>, used: &mut Vec<String>, scopes: &mut Vec<HashSet<String>>) {
+        scopes.push(HashSet::new());
+        for s in body {
+            self.collect_stmt(s, used, scopes);
+        }
+        scopes.pop();
+    }
+
+    fn collect_stmt(&self, stmt: &Statement, used: &mut Vec<String>, scopes: &mut Vec<HashSet<String>>) {
+        match stmt {
+            Statement::IfElseStatement { condition, if_body, else_body } => {
+                self.collect_exp(*condition, used, scopes);
+                self.collect_block(if_body, used, scopes);
+                if let Some(else_body) = else_body {
+                    self.collect_block(else_body, used, scopes);
+                }
+            }
+            Statement::VarStatement { name, value, .. } => {
+                if let Some(v) = value {
+                    self.collect_exp(*v, used, scopes);
+                }
+                scopes.last_mut().unwrap().insert(name.clone());
+            }
+            Statement::DestructureStatement { names, value, .. } => {
+                self.collect_exp(*value, used, scopes);
+                scopes.last_mut().unwrap().extend(names.iter().cloned());
+            }
+            Statement::MatchStatement { value, arms } => {
+                self.collect_exp(*value, used, scopes);
+                for arm in arms {
+                    self.collect_block(&arm.body, used, scopes);
+                }
+            }
+            Statement::ReturnStatement { value: Some(value), .. } | Statement::ExpressionStatement(value) => {
+                self.collect_exp(*value, used, scopes);
+            }
+            Statement::ReturnStatement { value: None, .. } => {}
+            Statement::FuncStatement { .. } | Statement::EnumStatement { .. } | Statement::StructStatement { .. } |
+            Statement::ExternStatement { .. } | Statement::ImportStatement { .. } => {}
+        }
+    }
+
+    fn collect_exp(&self, exp: ExpRef, used: &mut Vec<String>, scopes: &mut Vec<HashSet<String>>) {
+        match &*self.program[exp] {
+            Expression::Identifier { value, .. } => {
+                if !scopes.iter().any(|s| s.contains(value)) {
+                    used.push(value.clone());
+                }
+            }
+            Expression::FunctionCall { left, parameters } => {
+                self.collect_exp(*left, used, scopes);
+                for p in parameters {
+                    self.collect_exp(*p, used, scopes);
+                }
+            }
+            Expression::InfixExpression { left, right, .. } | Expression::AssignExpression { left, right } => {
+                self.collect_exp(*left, used, scopes);
+                self.collect_exp(*right, used, scopes);
+            }
+            Expression::PrefixExpression { right, .. } => {
+                self.collect_exp(*right, used, scopes);
+            }
+            Expression::FieldAccess { left, .. } => {
+                self.collect_exp(*left, used, scopes);
+            }
+            Expression::Index { left, index } => {
+                self.collect_exp(*left, used, scopes);
+                self.collect_exp(*index, used, scopes);
+            }
+            Expression::Slice { left, start, end } => {
+                self.collect_exp(*left, used, scopes);
+                for e in start.iter().chain(end.iter()) {
+                    self.collect_exp(*e, used, scopes);
+                }
+            }
+            Expression::Tuple(elements) => {
+                for e in elements {
+                    self.collect_exp(*e, used, scopes);
+                }
+            }
+            // Nested lambdas capture through this one.
+            Expression::Lambda { call_inputs, body, .. } => {
+                self.collect_body(call_inputs, body, used, scopes);
+            }
+            Expression::Integer(_) | Expression::Char(_) | Expression::String(_) |
+            Expression::GenericInstance { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use super::*;
+
+// Extern functions are called with the System V calling convention, the
+// backend puts the arguments into place. Every value we can pass fits into
+// one register, ints are passed as 64 bit values which matches long on the C side.
+// The type checker made sure the calls and the declarations fit.
+impl Compiler {
+    pub(super) fn declare_extern(&mut self, name: String, call_inputs: Vec<Parameter>, return_type: Option<Type>, variadic: bool) {
+        if !self.extern_functions.contains_key(&name) {
+            self.module.externs.push(ir::Extern { name: name.clone(), variadic });
+        }
+        self.extern_functions.insert(name.clone(), variadic);
+        self.functions.insert(name, (call_inputs, return_type));
+    }
+
+    pub(super) fn compile_extern_call(&mut self, name: String, parameters: &Vec<ExpRef>) -> Vec<Operand> {
+        let return_type = self.functions[&name].1.clone();
+        let args = self.compile_arguments(parameters);
+        let returns = self.slots(return_type.as_ref().unwrap_or(&Type::Void));
+        let values = self.call(Callee::Extern(name), args, returns);
+        if return_type == Some(Type::Char) {
+            // Only AL is set by the callee.
+            return vec![self.binary(BinOp::And, values[0].clone(), Operand::Imm(0xff), Ty::Char)];
+        }
+        return values;
+    }
+}
+
+impl Compiler {
+    // print(fmt, ...) and println(fmt, ...) are calls to printf, println adds a newline.
+    pub(super) fn compile_print(&mut self, left: ExpRef, parameters: &Vec<ExpRef>) -> Option<Vec<Operand>> {
+        let name = match &*self.program[left] {
+            Expression::Identifier { value, .. } if value == "print" || value == "println" => value.clone(),
+            _ => return None,
+        };
+        if self.functions.contains_key(&name) || self.table.get(name.clone()).is_some() {
+            return None;
+        }
+        let mut args = parameters.clone();
+        let printf = String::from("printf");
+        match *self.program[args[0]].clone() {
+            Expression::String(fmt) => {
+                if name == "println" {
+                    args[0] = self.new_expression(Expression::String(format!("{}\n", fmt)), Type::String);
+                }
+                Some(self.compile_extern_call(printf, &args))
+            }
+            _ => {
+                let written = self.compile_extern_call(printf.clone(), &args);
+                if name == "println" {
+                    let newline = self.new_expression(Expression::String(String::from("\n")), Type::String);
+                    return Some(self.compile_extern_call(printf, &vec![newline]));
+                }
+                Some(written)
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use super::*;
+
+// Generic functions are compiled once per set of type arguments. Every
+// instance gets a mangled name and is compiled after the rest of the
+// program with its type parameters bound in `type_bindings`.
+pub struct GenericFunction {
+    pub type_params: Vec<String>,
+    pub call_inputs: Vec<Parameter>,
+    pub return_type: Option<Type>,
+    pub body: Vec<Box<Statement>>,
+    pub inline: Inline,
+}
+
+impl Compiler {
+    pub(super) fn collect_generics(&mut self) {
+        for stmt in self.imports.iter().chain(self.stmts.iter()) {
+            match stmt {
+                Statement::StructStatement { name, type_params, fields, .. } => {
+                    if self.structs.contains_key(name) {
+                        panic!("Struct {} is defined twice", name);
+                    }
+                    self.structs.insert(name.clone(), StructDef {
+                        type_params: type_params.clone(),
+                        fields: fields.iter().map(|f| (f.name.clone(), f.param_type.clone())).collect(),
+                    });
+                }
+                Statement::FuncStatement { name, attributes, type_params, call_inputs, return_type, body, .. } if !type_params.is_empty() => {
+                    self.generic_functions.insert(name.clone(), GenericFunction {
+                        type_params: type_params.clone(),
+                        call_inputs: call_inputs.clone(),
+                        return_type: return_type.clone(),
+                        body: body.clone(),
+                        inline: inline_hint(attributes),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Types written inside of a generic function refer to its type parameters.
+    pub(super) fn resolve_type(&self, t: &Type) -> Type {
+        if self.type_bindings.is_empty() {
+            return t.clone();
+        }
+        return substitute(t, &self.type_bindings);
+    }
+
+    // Number of QWORD slots a value of this type occupies.
+    pub(super) fn size_of(&self, t: &Type) -> usize {
+        return self.slots(t).len();
+    }
+
+    // The register types of the slots of a value of this type.
+    pub(super) fn slots(&self, t: &Type) -> Vec<Ty> {
+        if let Some(fields) = aggregate_fields(t, &self.structs) {
+            return fields.iter().flat_map(|(_, f)| self.slots(f)).collect();
+        }
+        match t {
+            Type::Void => Vec::new(),
+            Type::Char => vec![Ty::Char],
+            Type::String | Type::Pointer(_) | Type::Function { .. } => vec![Ty::Ptr],
+            _ => vec![Ty::Int],
+        }
+    }
+
+    // The generic function and type arguments a call refers to, inferred from the arguments if not given.
+    pub(super) fn generic_callee(&self, left: ExpRef, parameters: &Vec<ExpRef>) -> Option<(String, Vec<Type>)> {
+        let (name, explicit) = match &*self.program[left] {
+            Expression::GenericInstance { value, type_args } => (value, Some(type_args)),
+            Expression::Identifier { value, .. } if self.table.get(value.clone()).is_none() => (value, None),
+            _ => return None,
+        };
+        let generic = self.generic_functions.get(name)?;
+        if let Some(args) = explicit {
+            if args.len() != generic.type_params.len() {
+                panic!("{} expects {} type arguments", name, generic.type_params.len());
+            }
+            return Some((name.clone(), args.iter().map(|a| self.resolve_type(a)).collect()));
+        }
+        if generic.call_inputs.len() != parameters.len() {
+            panic!("NOT THE SAME EMOUNT OF PARAMETERS");
+        }
+        let mut bindings = HashMap::new();
+        for (param, arg) in generic.call_inputs.iter().zip(parameters) {
+            let arg_type = self.type_of_checked(*arg);
+            if !unify(&param.param_type, &arg_type, &generic.type_params, &mut bindings) {
+                panic!("Argument of type {} does not fit {} of {}", arg_type, param.param_type, name);
+            }
+        }
+        let args = generic.type_params
+            .iter()
+            .map(|p| bindings
+                .get(p)
+                .cloned()
+                .unwrap_or_else(|| panic!("Can't infer type parameter {} of {}", p, name)))
+            .collect();
+        return Some((name.clone(), args));
+    }
+
+    pub(super) fn generic_signature(&self, name: &String, type_args: &Vec<Type>) -> (Vec<Parameter>, Option<Type>) {
+        let generic = &self.generic_functions[name];
+        let bindings: HashMap<String, Type> = generic.type_params.iter().cloned().zip(type_args.iter().cloned()).collect();
+        let params = generic.call_inputs
+            .iter()
+            .map(|p| Parameter {
+                name: p.name.clone(),
+                param_type: substitute(&p.param_type, &bindings),
+                span: p.span,
+            })
+            .collect();
+        let return_type = generic.return_type.as_ref().map(|r| substitute(r, &bindings));
+        return (params, return_type);
+    }
+
+    // Registers the instance and queues it for compilation, returns its mangled name.
+    pub(super) fn instantiate(&mut self, name: &String, type_args: &Vec<Type>) -> String {
+        let mangled = mangle(name, type_args);
+        if !self.functions.contains_key(&mangled) {
+            let signature = self.generic_signature(name, type_args);
+            self.functions.insert(mangled.clone(), signature);
+            self.pending_instances.push((mangled.clone(), name.clone(), type_args.clone()));
+        }
+        return mangled;
+    }
+
+    pub(super) fn compile_instances(&mut self) {
+        while let Some((mangled, name, type_args)) = self.pending_instances.pop() {
+            let (call_inputs, return_type) = self.functions[&mangled].clone();
+            let generic = &self.generic_functions[&name];
+            let body = generic.body.clone();
+            let inline = generic.inline;
+            self.type_bindings = generic.type_params.iter().cloned().zip(type_args).collect();
+            let mut function = self.compile_function(mangled, call_inputs, return_type, body, Vec::new());
+            function.inline = inline;
+            self.module.functions.push(function);
+            self.type_bindings.clear();
+        }
+    }
+
+    // Pair(1, 2) and Pair<int>(1, 2) construct structs, returns the type of the struct.
+    pub(super) fn constructor_type(&self, left: ExpRef, parameters: &Vec<ExpRef>) -> Option<Type> {
+        let (name, explicit) = match &*self.program[left] {
+            Expression::GenericInstance { value, type_args } => (value, Some(type_args)),
+            Expression::Identifier { value, .. } if self.table.get(value.clone()).is_none() => (value, None),
+            _ => return None,
+        };
+        let def = self.structs.get(name)?;
+        if def.fields.len() != parameters.len() {
+            panic!("Struct {} has {} fields", name, def.fields.len());
+        }
+        if let Some(args) = explicit {
+            let args: Vec<Type> = args.iter().map(|a| self.resolve_type(a)).collect();
+            return Some(Type::generic(name, args));
+        }
+        let mut bindings = HashMap::new();
+        for ((field, field_type), arg) in def.fields.iter().zip(parameters) {
+            let arg_type = self.type_of_checked(*arg);
+            if !unify(field_type, &arg_type, &def.type_params, &mut bindings) {
+                panic!("Value of type {} does not fit field {} of {}", arg_type, field, name);
+            }
+        }
+        let args: Vec<Type> = def.type_params
+            .iter()
+            .map(|p| bindings
+                .get(p)
+                .cloned()
+                .unwrap_or_else(|| panic!("Can't infer type parameter {} of {}", p, name)))
+            .collect();
+        return Some(Type::generic(name, args));
+    }
+}
@@ -1,493 +1,645 @@
-use std::{collections::HashMap, fmt};
+use std::collections::HashMap;
 
 use symbols::*;
 
-use crate::{ast::{ExpRef, Expression, Parameter, Program, Statement}, token::TokenType};
+use crate::{ast::{Attribute, ExpRef, Expression, MatchArm, Parameter, Pattern, Program, Statement, Type}, token::{Span, TokenType}};
+use crate::ir::{self, BinOp, BlockId, Callee, Data, Inline, Inst, Operand, Runtime, Terminator, Ty, VReg};
+use crate::semantic::{collect_enums, EnumTable, ExpTypes};
+use types::*;
+use generics::*;
 mod symbols;
+pub(crate) mod types;
+mod closures;
+mod generics;
+mod ffi;
 
 
 
-#[derive(Debug)]
-enum OpCodeTypes {
-    Add,
-    Sub,
-    Mul,
-    Mov, 
-    Push,
-    Pop,
-    Xor,
-    Call,
-    Func(String),
-    Global, 
-    Extern,
-    Leave,
-    Ret,
-    Cmp,
-    Movzx,
-    Setl,
-    Setg,
-    Setle,
-    Setge,
-    Sete,
-    Je,
-    Jmp,
-    Db,
-}
-impl fmt::Display for OpCodeTypes {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            OpCodeTypes::Func(s) => {
-                write!(f, "{}:", s)
-            }
-            OpCodeTypes::Extern => {
-                write!(f, "extern")
-            }
-            OpCodeTypes::Global=> {
-                write!(f, "global")
-            }
-            _ => { 
-                write!(f, "{}", format!("\t{:?}", self).to_uppercase())
-            }
-        }
-    }
-}
-
-pub struct Instruction {
-    opcode: OpCodeTypes,    
-    operands: Vec<String>
-}
-#[derive(Debug)]
-pub enum Registers {
-    AL,
-    EAX,
-    RAX,
-    RDI,
-    RSI,
-    RBX,
-    RSP,
-    RBP, 
-}
-
-impl Registers {
-    pub fn to_string(&self) -> String {
-        return format!("{:?}", self).to_uppercase()
-    }
-}
-
+// Lowers the AST to IR. Every expression gives the operands of its slots,
+// element 0 first, and variables are registers of the current function.
 pub struct Compiler {
     stmts: Vec<Statement>,
     program: Program,
-    output: Vec<Instruction>,
+    types: ExpTypes,
+    module: ir::Module,
+    // The function being compiled and the block new instructions go to.
+    function: ir::Function,
+    block: BlockId,
     table: SymbolTable,
-    data_section: Vec<Instruction>,
-    functions: HashMap<String, (Vec<Parameter>, Option<String>)>,
-    local_labels: Vec<Instruction>,
-    cur_cond_idx: i64,
+    functions: HashMap<String, (Vec<Parameter>, Option<Type>)>,
+    // Extern functions and whether they are variadic.
+    extern_functions: HashMap<String, bool>,
+    enums: EnumTable,
+    lambdas: Vec<ir::Function>,
+    static_closures: Vec<String>,
+    cur_lambda_idx: i64,
     cur_str_idx: i64,
+    structs: HashMap<String, StructDef>,
+    generic_functions: HashMap<String, GenericFunction>,
+    pending_instances: Vec<(String, String, Vec<Type>)>,
+    type_bindings: HashMap<String, Type>,
+    imports: Vec<Statement>,
+    exports: bool,
 }
 
 impl Compiler {
-    pub fn new(stmts: Vec<Statement>, program: Program) -> Self{
+    pub fn new(stmts: Vec<Statement>, program: Program, types: ExpTypes) -> Self{
         return Self {
             stmts,
             program,
-            output: Vec::new(),
-            data_section: Vec::new(),
+            types,
+            module: ir::Module::default(),
+            function: ir::Function::new(String::new()),
+            block: 0,
             table: SymbolTable::new(),
             functions: HashMap::new(),
-            local_labels: Vec::new(),
-            cur_cond_idx: 0,
+            extern_functions: HashMap::new(),
+            enums: HashMap::new(),
+            lambdas: Vec::new(),
+            static_closures: Vec::new(),
+            cur_lambda_idx: 0,
             cur_str_idx: 0,
+            structs: HashMap::new(),
+            generic_functions: HashMap::new(),
+            pending_instances: Vec::new(),
+            type_bindings: HashMap::new(),
+            imports: Vec::new(),
+            exports: false,
         };
     }
 
 
-    fn new_instruction(&mut self, opcode: OpCodeTypes, operands: Vec<String>) {
-        let instruction = Instruction{
-            opcode,
-            operands
-        };
-        self.output.push(instruction);
+    fn new_expression(&mut self, exp: Expression, t: Type) -> ExpRef {
+        self.program.push(Box::new(exp));
+        self.types.insert(self.program.len() - 1, t);
+        return self.program.len() - 1;
     }
-    fn alloc(&mut self, bytes: u32) {
-        self.new_instruction(OpCodeTypes::Sub, vec![Registers::RSP.to_string(), format!("{}", bytes)]);
+
+    fn emit(&mut self, inst: Inst) {
+        self.function.blocks[self.block].insts.push(inst);
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        self.function.blocks[self.block].term = term;
+    }
+
+    fn new_reg(&mut self, ty: Ty) -> VReg {
+        return self.function.new_reg(ty);
     }
 
-    fn push_reg(&mut self, reg: Registers) {
-        self.new_instruction(OpCodeTypes::Push, vec![reg.to_string()]);
+    fn new_block(&mut self) -> BlockId {
+        return self.function.new_block();
     }
 
-    fn push_const(&mut self, constant: String) {
-        self.new_instruction(OpCodeTypes::Push, vec![constant]);
+    fn binary(&mut self, op: BinOp, left: Operand, right: Operand, ty: Ty) -> Operand {
+        let dest = self.new_reg(ty);
+        self.emit(Inst::Binary { dest, op, left, right });
+        return Operand::Reg(dest);
     }
 
-    fn pop(&mut self, reg: Registers) {
-        self.new_instruction(OpCodeTypes::Pop, vec![reg.to_string()]);
+    fn call(&mut self, callee: Callee, args: Vec<Operand>, returns: Vec<Ty>) -> Vec<Operand> {
+        let dests: Vec<VReg> = returns.into_iter().map(|t| self.new_reg(t)).collect();
+        self.emit(Inst::Call { dests: dests.clone(), callee, args });
+        return dests.into_iter().map(Operand::Reg).collect();
     }
 
-    fn register_op(&mut self, opcode: OpCodeTypes, reg1: Registers, reg2: Registers) {
-        self.new_instruction(opcode, vec![reg1.to_string(), reg2.to_string()]);
+    // Arguments are evaluated right to left.
+    fn compile_arguments(&mut self, parameters: &[ExpRef]) -> Vec<Operand> {
+        let mut values = vec![Vec::new(); parameters.len()];
+        for (i, par) in parameters.iter().enumerate().rev() {
+            values[i] = self.compile_expression(*par);
+        }
+        return values.concat();
     }
 
-    fn compile_infix(&mut self, left: ExpRef, right: ExpRef, op: TokenType) {
+    fn compile_infix(&mut self, left: ExpRef, right: ExpRef, op: TokenType, exp: ExpRef) -> Vec<Operand> {
         if op == TokenType::Assign {
-            self.compile_expression(right);
-            self.pop(Registers::RAX);
+            let values = self.compile_expression(right);
             let exp = &self.program[left];
             match *exp.clone() {
-                Expression::Identifier { value, ident_type } => {
+                Expression::Identifier { value, .. } => {
                     let symbol = self.table.get(value.clone()).expect(&format!("Variable {} not defined", value));
-                    self.store_reg_on_stack(symbol.offset, Registers::RAX);
-                    return;
+                    let regs = symbol.regs.clone();
+                    self.assign(&regs, values);
+                    return Vec::new();
                 }
                 _ => {
                     panic!("Tried to assign value to not identifier")
                 }
             }
         }
-        self.compile_expression(left);
-        self.compile_expression(right);
-        self.pop(Registers::RBX);
-        self.pop(Registers::RAX);
+        if self.type_of_checked(left) == Type::String {
+            return self.compile_string_infix(left, right, op);
+        }
+        let l = self.compile_scalar(left);
+        let r = self.compile_scalar(right);
+        let op = match op {
+            TokenType::Plus => BinOp::Add,
+            TokenType::Minus => BinOp::Sub,
+            TokenType::Astrik => BinOp::Mul,
+            TokenType::LT => BinOp::Lt,
+            TokenType::GT => BinOp::Gt,
+            TokenType::LTEQ => BinOp::Le,
+            TokenType::GTEQ => BinOp::Ge,
+            TokenType::EQ => BinOp::Eq,
+            TokenType::NotEQ => BinOp::Ne,
+            _ => {
+                panic!("Operator {:?} is not supported", op);
+            }
+        };
+        let ty = self.slots(&self.type_of_checked(exp))[0];
+        return vec![self.binary(op, l, r, ty)];
+    }
+
+    // Operators work on values of one slot, tuples and structs would lose all
+    // but their first element.
+    fn compile_scalar(&mut self, exp: ExpRef) -> Operand {
+        let values = self.compile_expression(exp);
+        if values.len() != 1 {
+            panic!("Operators can't be used on values of type {}", self.type_of_checked(exp));
+        }
+        return values[0].clone();
+    }
+
+    fn compile_string_infix(&mut self, left: ExpRef, right: ExpRef, op: TokenType) -> Vec<Operand> {
+        if self.type_of_checked(right) != Type::String {
+            panic!("Can't combine string with {}", self.type_of_checked(right));
+        }
+        let r = self.compile_expression(right)[0].clone();
+        let l = self.compile_expression(left)[0].clone();
         match op {
             TokenType::Plus => {
-                self.register_op(OpCodeTypes::Add, Registers::RAX, Registers::RBX);
-                self.push_reg(Registers::RAX);
-            }
-            TokenType::Minus => {
-                self.register_op(OpCodeTypes::Sub, Registers::RAX, Registers::RBX);
-                self.push_reg(Registers::RAX);
+                self.call(Callee::Runtime(Runtime::StrConcat), vec![l, r], vec![Ty::Ptr])
             }
-            TokenType::Astrik => {
-                self.register_op(OpCodeTypes::Mul, Registers::RAX, Registers::RBX);
-                self.push_reg(Registers::RAX);
+            TokenType::EQ => {
+                self.call(Callee::Runtime(Runtime::StrEq), vec![l, r], vec![Ty::Int])
             }
-            TokenType::LT | TokenType::GT | TokenType::LTEQ | TokenType::GTEQ | TokenType::EQ => {
-                self.comp(op);
+            TokenType::NotEQ => {
+                let eq = self.call(Callee::Runtime(Runtime::StrEq), vec![l, r], vec![Ty::Int]);
+                vec![self.binary(BinOp::Xor, eq[0].clone(), Operand::Imm(1), Ty::Int)]
             }
             _ => {
-                panic!();
+                panic!("Operator {:?} is not supported on strings", op);
             }
         }
     }
 
-    // COMPARE RAX op RBX
-    fn comp(&mut self, op: TokenType) {
-        self.register_op(OpCodeTypes::Cmp, Registers::RAX, Registers::RBX);
-        match op {
-            TokenType::LT => {
-                self.new_instruction(OpCodeTypes::Setl, vec![
-                    Registers::AL.to_string()
-                ]);
-            }
-            TokenType::GT => {
-                self.new_instruction(OpCodeTypes::Setg, vec![
-                    Registers::AL.to_string()
-                ]);
-            }
-            TokenType::LTEQ => {
-                self.new_instruction(OpCodeTypes::Setle, vec![
-                    Registers::AL.to_string()
-                ]);
-            }
-            TokenType::GTEQ => {
-                self.new_instruction(OpCodeTypes::Setge, vec![
-                    Registers::AL.to_string()
-                ]);
-            }
-            TokenType::EQ=> {
-                self.new_instruction(OpCodeTypes::Sete, vec![
-                    Registers::AL.to_string()
-                ]);
-            }
-            _ => {}
+    // Copies the values into the registers of a variable. Values that read the
+    // variable itself, like in t = (t.1, t.0), are copied out first.
+    fn assign(&mut self, regs: &[VReg], values: Vec<Operand>) {
+        let mut values = values;
+        if values.iter().any(|v| matches!(v, Operand::Reg(r) if regs.contains(r))) {
+            values = values
+                .into_iter()
+                .zip(regs)
+                .map(|(v, r)| {
+                    let tmp = self.new_reg(self.function.regs[*r]);
+                    self.emit(Inst::Copy { dest: tmp, src: v });
+                    Operand::Reg(tmp)
+                })
+                .collect();
+        }
+        for (dest, src) in regs.iter().zip(values) {
+            self.emit(Inst::Copy { dest: *dest, src });
+        }
+    }
 
+    // The type the checker inferred for the expression, with the type
+    // parameters of the instance being compiled bound.
+    fn type_of_checked(&self, exp: ExpRef) -> Type {
+        match self.types.get(&exp) {
+            Some(t) => return self.resolve_type(t),
+            None => panic!("The type of expression {} was not inferred", exp),
         }
-        self.register_op(OpCodeTypes::Movzx, Registers::RAX, Registers::AL);
-        self.push_reg(Registers::RAX);
     }
 
-    pub fn compile_expression(&mut self, exp: ExpRef) {
+    pub fn compile_expression(&mut self, exp: ExpRef) -> Vec<Operand> {
         let expression = *self.program[exp].clone();
         match expression {
             Expression::InfixExpression { left, op, right } => {
-                self.compile_infix(left, right, op);
+                self.compile_infix(left, right, op, exp)
+            }
+            Expression::PrefixExpression { op: TokenType::Minus, right } => {
+                let r = self.compile_scalar(right);
+                let ty = self.slots(&self.type_of_checked(right))[0];
+                vec![self.binary(BinOp::Sub, Operand::Imm(0), r, ty)]
+            }
+            Expression::PrefixExpression { op, .. } => {
+                panic!("Prefix operator {:?} is not supported", op);
             }
             Expression::String(s)  => {
-                self.data_section.push(Instruction {
-                    opcode: OpCodeTypes::Func(
-                        format!(".S{}", self.cur_str_idx),
-                    ), 
-                    operands: vec![]
-                });
-
-                self.data_section.push(
-                    Instruction {
-                        opcode: OpCodeTypes::Db,
-                        operands: vec![
-                            format!("\"{}\"", s),
-                            format!("0")
-                            
-                        ]
-                    }
-                );
-                self.new_instruction(OpCodeTypes::Mov, vec![
-                    Registers::RAX.to_string(),
-                    format!(".S{}", self.cur_str_idx)
-                ]);
+                // Not a local label, NASM would put it under the label before it.
+                let label = format!("__str{}", self.cur_str_idx);
                 self.cur_str_idx += 1;
-                self.push_reg(Registers::RAX);
+                self.module.data.push(Data::String { label: label.clone(), value: s });
+                vec![Operand::Label(label)]
             }
             Expression::Integer(i) => {
-                self.new_instruction(OpCodeTypes::Push, vec![format!("{}", i)]);
+                vec![Operand::Imm(i)]
+            }
+            Expression::Char(c) => {
+                vec![Operand::Imm(c as i64)]
+            }
+            Expression::Identifier { value, .. } => {
+                let s = self.table.get(value.clone());
+                if s.is_none() && self.functions.contains_key(&value) {
+                    return vec![Operand::Label(self.static_closure(&value))];
+                }
+                let s = s.unwrap_or_else(|| panic!("Variable {} not defined", value));
+                s.regs.iter().map(|r| Operand::Reg(*r)).collect()
+            }
+            Expression::Tuple(elements) => {
+                self.compile_arguments(&elements)
             }
-            Expression::Identifier { value, ident_type } => {
-                let s = self.table.get(value);
-                if s.is_none() {
-                    return;
+            Expression::Lambda { call_inputs, return_type, body } => {
+                self.compile_lambda(call_inputs, return_type, body)
+            }
+            Expression::GenericInstance { value, type_args } => {
+                if !self.generic_functions.contains_key(&value) {
+                    panic!("{} is no generic function", value);
+                }
+                let type_args = type_args.iter().map(|a| self.resolve_type(a)).collect();
+                let name = self.instantiate(&value, &type_args);
+                vec![Operand::Label(self.static_closure(&name))]
+            }
+            Expression::Index { left, index } => {
+                let s = self.compile_expression(left)[0].clone();
+                let i = self.compile_expression(index)[0].clone();
+                let addr = self.binary(BinOp::Add, s, i, Ty::Ptr);
+                let dest = self.new_reg(Ty::Char);
+                self.emit(Inst::Load { dest, addr, offset: 0, byte: true });
+                vec![Operand::Reg(dest)]
+            }
+            Expression::Slice { left, start, end } => {
+                let end = match end {
+                    Some(e) => self.compile_expression(e)[0].clone(),
+                    None => Operand::Imm(-1),
                 };
-                self.get_from_stack(s.unwrap().offset, Registers::RAX);
-                self.new_instruction(OpCodeTypes::Push, vec![Registers::RAX.to_string()]);
+                let start = match start {
+                    Some(e) => self.compile_expression(e)[0].clone(),
+                    None => Operand::Imm(0),
+                };
+                let s = self.compile_expression(left)[0].clone();
+                self.call(Callee::Runtime(Runtime::StrSlice), vec![s, start, end], vec![Ty::Ptr])
             }
             Expression::FunctionCall { left, parameters } => {
-                let (p, name) = match *self.program[left].clone() {
-                    Expression::Identifier { value, ident_type } => {
-                        let par = self.functions.get(&value);
-                        (par.unwrap(), value)
-                    }
-                    _ => {
-                        panic!();
+                if let Some(values) = self.compile_cast(left, &parameters) {
+                    return values;
+                }
+                if let Some(values) = self.compile_print(left, &parameters) {
+                    return values;
+                }
+                if self.constructor_type(left, &parameters).is_some() {
+                    return self.compile_arguments(&parameters);
+                }
+                if let Some(name) = self.direct_callee(left).filter(|n| self.extern_functions.contains_key(n)) {
+                    return self.compile_extern_call(name, &parameters);
+                }
+                // Named functions are called directly, every other callee is a function pointer.
+                let direct = match self.generic_callee(left, &parameters) {
+                    Some((name, type_args)) => Some(self.instantiate(&name, &type_args)),
+                    None => self.direct_callee(left),
+                };
+                let (func_params, return_type): (Vec<Type>, Type) = match &direct {
+                    Some(name) => {
+                        let p = &self.functions[name];
+                        (p.0.iter().map(|x| x.param_type.clone()).collect(), p.1.clone().unwrap_or(Type::Void))
                     }
+                    None => match self.type_of_checked(left) {
+                        Type::Function { params, return_type } => (params, *return_type),
+                        t => panic!("Can't call a value of type {}", t),
+                    },
                 };
-                let func_params = &p.0;
-                let return_type = &p.1;
                 if func_params.len() != parameters.len() {
                     panic!("NOT THE SAME EMOUNT OF PARAMETERS");
                 }
-                for par in parameters.iter().rev() {
-                    self.compile_expression(*par);
+                let args = self.compile_arguments(&parameters);
+                let callee = match direct {
+                    Some(name) => Callee::Direct(name),
+                    None => Callee::Indirect(self.compile_expression(left)[0].clone()),
+                };
+                let returns = self.slots(&return_type);
+                self.call(callee, args, returns)
+            }
+            Expression::FieldAccess { left, field } => {
+                let enum_name = match *self.program[left].clone() {
+                    Expression::Identifier { value, .. } if self.enums.contains_key(&value) => value,
+                    _ => {
+                        return self.compile_field_access(left, field);
+                    }
+                };
+                let variants = self.enums
+                    .get(&enum_name)
+                    .unwrap_or_else(|| panic!("Unknown enum {}", enum_name));
+                let tag = variants
+                    .iter()
+                    .position(|v| *v == field)
+                    .unwrap_or_else(|| panic!("Enum {} has no variant {}", enum_name, field));
+                vec![Operand::Imm(tag as i64)]
+            }
+            Expression::AssignExpression { .. } => Vec::new(),
+        }
+    }
+
+    // int(c), char(i) and len(s) are builtins and not real function calls.
+    // chars are kept zero extended in a full register, so only char() has to truncate.
+    fn compile_cast(&mut self, left: ExpRef, parameters: &Vec<ExpRef>) -> Option<Vec<Operand>> {
+        let target = match &*self.program[left] {
+            Expression::Identifier { value, .. } if !self.functions.contains_key(value) => value.clone(),
+            _ => return None,
+        };
+        if target != "int" && target != "char" && target != "len" {
+            return None;
+        }
+        if parameters.len() != 1 {
+            panic!("{}() takes exactly one argument", target);
+        }
+        let value = self.compile_expression(parameters[0])[0].clone();
+        if target == "char" {
+            return Some(vec![self.binary(BinOp::And, value, Operand::Imm(0xff), Ty::Char)]);
+        }
+        if target == "len" {
+            if self.type_of_checked(parameters[0]) != Type::String {
+                panic!("len() expects a string");
+            }
+            let dest = self.new_reg(Ty::Int);
+            self.emit(Inst::Load { dest, addr: value, offset: -8, byte: false });
+            return Some(vec![Operand::Reg(dest)]);
+        }
+        return Some(vec![value]);
+    }
+
+    fn direct_callee(&self, left: ExpRef) -> Option<String> {
+        match &*self.program[left] {
+            Expression::Identifier { value, .. } => {
+                if self.table.get(value.clone()).is_none() && self.functions.contains_key(value) {
+                    return Some(value.clone());
                 }
-                self.new_instruction(OpCodeTypes::Call, vec![name]);
+                return None;
             }
-            _ => {}
+            _ => None,
         }
     }
 
-    fn store_reg_on_stack(&mut self, offset: u64, reg: Registers) {
-        self.new_instruction(OpCodeTypes::Mov, vec![
-            format!("QWORD [rbp-{}]", offset),
-            reg.to_string()
-        ]);
+    // Only the slots of the element `field` of the tuple or struct `left`.
+    fn compile_field_access(&mut self, left: ExpRef, field: String) -> Vec<Operand> {
+        let tuple_type = self.type_of_checked(left);
+        let elements = aggregate_fields(&tuple_type, &self.structs)
+            .unwrap_or_else(|| panic!("Field access on {} which is no tuple, struct or enum", tuple_type));
+        let idx = elements
+            .iter()
+            .position(|(name, _)| *name == field)
+            .unwrap_or_else(|| panic!("{} has no field {}", tuple_type, field));
+        let start: usize = elements[..idx].iter().map(|(_, e)| self.size_of(e)).sum();
+        let size = self.size_of(&elements[idx].1);
+        let values = self.compile_expression(left);
+        return values[start..start + size].to_vec();
     }
 
-    fn get_from_stack(&mut self, offset: u64, reg: Registers) {
-        self.new_instruction(OpCodeTypes::Mov, vec![
-            reg.to_string(),
-            format!("QWORD [rbp-{}]", offset),
-        ]);
+    // Gives a new variable registers holding `values`. Variables without a
+    // value start out as 0.
+    fn add_local(&mut self, name: String, var_type: Type, values: Option<Vec<Operand>>) {
+        let slots = self.slots(&var_type);
+        let values = values.unwrap_or_else(|| vec![Operand::Imm(0); slots.len()]);
+        let mut regs = Vec::new();
+        for (ty, src) in slots.into_iter().zip(values) {
+            let dest = self.new_reg(ty);
+            self.emit(Inst::Copy { dest, src });
+            regs.push(dest);
+        }
+        self.table.add(name, Symbol{
+            symb_type: var_type,
+            regs,
+        });
     }
 
-    fn setup_stackfram(&mut self) {
-        self.push_reg(Registers::RBP);
-        self.register_op(OpCodeTypes::Mov, Registers::RBP, Registers::RSP);
+    fn variant_tag(&self, enum_name: &String, variant: &String) -> usize {
+        return self.enums[enum_name]
+            .iter()
+            .position(|v| v == variant)
+            .unwrap();
     }
 
-    fn cleanup_stackframe(&mut self) {
-        self.register_op(OpCodeTypes::Mov, Registers::RSP, Registers::RBP);
-        self.pop(Registers::RBP);
+    // Lowered as a compare chain, every arm gets its own block.
+    fn compile_match(&mut self, value: ExpRef, arms: Vec<MatchArm>) {
+        let value = self.compile_expression(value)[0].clone();
+        let end = self.new_block();
+        let arm_blocks: Vec<BlockId> = arms.iter().map(|_| self.new_block()).collect();
+        let mut has_wildcard = false;
+        for (arm, block) in arms.iter().zip(&arm_blocks) {
+            match &arm.pattern {
+                Pattern::Variant { enum_name, variant } => {
+                    let tag = self.variant_tag(enum_name, variant);
+                    let cond = self.binary(BinOp::Eq, value.clone(), Operand::Imm(tag as i64), Ty::Int);
+                    let next = self.new_block();
+                    self.terminate(Terminator::Branch { cond, then: *block, otherwise: next });
+                    self.block = next;
+                }
+                Pattern::Wildcard => {
+                    has_wildcard = true;
+                    self.terminate(Terminator::Jump(*block));
+                    break;
+                }
+            }
+        }
+        if !has_wildcard {
+            self.terminate(Terminator::Jump(end));
+        }
+        for (arm, block) in arms.into_iter().zip(arm_blocks) {
+            self.block = block;
+            self.compile_block(arm.body);
+            self.terminate(Terminator::Jump(end));
+        }
+        self.block = end;
     }
 
-    fn print_builtin(&mut self) {
-        self.setup_stackfram();
-        self.new_instruction(OpCodeTypes::Mov, vec![
-            Registers::RDI.to_string(),
-            format!("[RBP + 16]")
-        ]);
-
-        self.new_instruction(OpCodeTypes::Mov, vec![
-            Registers::RSI.to_string(),
-            String::from("[RBP + 24]")
-        ]);
-
-        self.new_instruction(OpCodeTypes::Xor, vec![
-            Registers::RAX.to_string(),
-            Registers::RAX.to_string(),
-        ]);
-        self.new_instruction(OpCodeTypes::Call, vec![
-            String::from("printf")
-        ]);
-        //self.pop(Registers::RBP);
-        self.new_instruction(OpCodeTypes::Leave, vec![]);
-        self.new_instruction(OpCodeTypes::Ret, vec![]);
+    // Compiles a function on its own and gives it back, the function being
+    // compiled when it is called is continued afterwards. Captured variables
+    // are loaded out of the closure object, so closures capture by value.
+    // The body only sees its parameters and its captures, the locals of the
+    // function around it live in other registers.
+    fn compile_function(&mut self, name: String, call_inputs: Vec<Parameter>, return_type: Option<Type>, body: Vec<Box<Statement>>, captures: Vec<(String, Type)>) -> ir::Function {
+        let outer_function = std::mem::replace(&mut self.function, ir::Function::new(name.clone()));
+        let outer_block = std::mem::replace(&mut self.block, 0);
+        let outer_table = std::mem::replace(&mut self.table, SymbolTable::new());
+        if !captures.is_empty() {
+            let env = self.new_reg(Ty::Ptr);
+            self.function.env = Some(env);
+            let mut env_offset = 8;
+            for (capture, capture_type) in captures {
+                let mut values = Vec::new();
+                for ty in self.slots(&capture_type) {
+                    let dest = self.new_reg(ty);
+                    self.emit(Inst::Load { dest, addr: Operand::Reg(env), offset: env_offset, byte: false });
+                    values.push(Operand::Reg(dest));
+                    env_offset += 8;
+                }
+                self.add_local(capture, capture_type, Some(values));
+            }
+        }
+        let return_type = return_type.map(|r| self.resolve_type(&r));
+        self.function.returns = self.slots(return_type.as_ref().unwrap_or(&Type::Void));
+        for inp in call_inputs {
+            let param_type = self.resolve_type(&inp.param_type);
+            let mut regs = Vec::new();
+            for ty in self.slots(&param_type) {
+                regs.push(self.new_reg(ty));
+            }
+            self.function.params.extend(regs.iter().copied());
+            self.table.add(inp.name, Symbol { symb_type: param_type, regs });
+        }
+        for i in body {
+            self.compile_stmt(*i);
+        }
+        if self.function.returns.is_empty() {
+            self.terminate(Terminator::Return(Vec::new()));
+        }
+        self.table = outer_table;
+        self.block = outer_block;
+        return std::mem::replace(&mut self.function, outer_function);
+    }
+
+    // Locals of the block go out of scope after it.
+    fn compile_block(&mut self, body: Vec<Box<Statement>>) {
+        self.table = SymbolTable::new_from_outer(self.table.clone());
+        for i in body {
+            self.compile_stmt(*i);
+        }
+        self.table = self.table.move_out();
     }
 
     pub fn compile_stmt(&mut self, stmt: Statement) {
         match stmt {
             Statement::IfElseStatement { condition, if_body, else_body } => {
-                self.compile_expression(condition);
-                self.pop(Registers::RAX);
-                self.new_instruction(OpCodeTypes::Cmp, vec![
-                    Registers::RAX.to_string(),
-                    format!("{}", 0),
-                ]);
-                let idx1 = self.cur_cond_idx;
-                self.cur_cond_idx += 1;
-                self.new_instruction(OpCodeTypes::Je, vec![
-                    format!(".A{}", idx1)
-                ]);
-                for i in if_body {
-                    self.compile_stmt(*i);
-                }
-                let idx2 = self.cur_cond_idx;
-                self.cur_cond_idx += 1;
-                self.new_instruction(OpCodeTypes::Jmp, vec![
-                    format!(".A{}", idx2)
-                ]);
-                self.new_instruction(OpCodeTypes::Func(format!(".A{}", idx1)), vec![]);
+                let cond = self.compile_expression(condition)[0].clone();
+                let (then, otherwise, end) = (self.new_block(), self.new_block(), self.new_block());
+                self.terminate(Terminator::Branch { cond, then, otherwise });
+                self.block = then;
+                self.compile_block(if_body);
+                self.terminate(Terminator::Jump(end));
+                self.block = otherwise;
                 if else_body.is_some() {
-                    for i in else_body.unwrap() {
-                        self.compile_stmt(*i);
-                    }
+                    self.compile_block(else_body.unwrap());
                 }
-                self.new_instruction(OpCodeTypes::Func(format!(".A{}", idx2)), vec![]);
-
+                self.terminate(Terminator::Jump(end));
+                self.block = end;
             }
-            Statement::FuncStatement { name, call_inputs, return_type, body } => {
+            Statement::FuncStatement { type_params, .. } if !type_params.is_empty() => {}
+            Statement::FuncStatement { name, public, attributes, call_inputs, return_type, body, .. } => {
                 self.functions.insert(
                     name.clone(),
-                    (call_inputs.clone(), return_type)
+                    (call_inputs.clone(), return_type.clone())
                 );
-                self.new_instruction(OpCodeTypes::Func(name.clone()), vec![]);
-                self.setup_stackfram();
-                self.alloc(16);
-                let idx = self.output.len();
-                self.table = SymbolTable::new_from_outer(self.table.clone());
-                let mut offset = 8;
-                for inp in call_inputs {
-                    offset += 8;
-                    self.table.add(inp.name, Symbol{
-                        symb_type: inp.param_type,
-                        offset
-                    });
-                    self.new_instruction(OpCodeTypes::Mov, vec![
-                        Registers::RAX.to_string(),
-                        format!("QWORD [RBP + {}]", offset)
-                    ]);
-                    self.new_instruction(OpCodeTypes::Mov, vec![
-                        format!("QWORD [RBP - {}]", offset),
-                        Registers::RAX.to_string(),
-                    ]);
+                // Only what other modules can see gets a global symbol, and main for the linker.
+                let exported = self.exports && (public || name == "main");
+                let mut function = self.compile_function(name, call_inputs, return_type, body, Vec::new());
+                function.exported = exported;
+                function.inline = inline_hint(&attributes);
+                self.module.functions.push(function);
+            }
+            Statement::VarStatement { name, value, var_type, .. } => {
+                let var_type = var_type.unwrap_or_else(|| panic!("The type of {} was not inferred", name));
+                let var_type = self.resolve_type(&var_type);
+                let values = value.map(|v| self.compile_expression(v));
+                self.add_local(name, var_type, values);
+            }
+            Statement::DestructureStatement { names, value, .. } => {
+                let tuple_type = self.type_of_checked(value);
+                let elements = aggregate_fields(&tuple_type, &self.structs)
+                    .unwrap_or_else(|| panic!("Can't destructure {} which is no tuple or struct", tuple_type));
+                if elements.len() != names.len() {
+                    panic!("Can't destructure {} into {} variables", tuple_type, names.len());
                 }
-                for i in body {
-                    self.compile_stmt(*i);
-                }
-                self.output[idx-1] = Instruction{
-                    opcode: OpCodeTypes::Sub,
-                    operands: vec![
-                        Registers::RSP.to_string(),
-                        format!("{}", std::cmp::max(self.table.cur_offset, 16))
-                    ]
-                };
-
-                self.table = self.table.move_out();
-                if name != "main" {
-                    self.cleanup_stackframe();
-                    self.new_instruction(OpCodeTypes::Ret, vec![]);
-                } else {
-                    self.register_op(
-                        OpCodeTypes::Xor,
-                        Registers::EAX,
-                        Registers::EAX
-                    );
-                    self.new_instruction(OpCodeTypes::Leave, vec![]);
-                    self.new_instruction(OpCodeTypes::Ret, vec![]);
+                let mut values = self.compile_expression(value).into_iter();
+                for (name, (_, element_type)) in names.into_iter().zip(elements) {
+                    let element: Vec<Operand> = values.by_ref().take(self.size_of(&element_type)).collect();
+                    self.add_local(name, element_type, Some(element));
                 }
             }
-            Statement::VarStatement { name, value, var_type } => {
-                let offset = self.table.cur_offset + 8;
-                self.table.add(name, Symbol{
-                    symb_type: var_type,
-                    offset 
-                });
-                if value.is_some() {
-                    self.compile_expression(value.unwrap());
-                    self.pop(Registers::RAX);
-                    self.store_reg_on_stack(offset, Registers::RAX);
-                }
+            Statement::ReturnStatement { value, .. } => {
+                let values = match value {
+                    Some(v) => self.compile_expression(v),
+                    None => Vec::new(),
+                };
+                self.terminate(Terminator::Return(values));
+                // Whatever follows can't be reached.
+                self.block = self.new_block();
             }
-            Statement::ReturnStatement { value } => {
-                self.compile_expression(value);
-                self.new_instruction(OpCodeTypes::Leave, vec![]);
-                self.new_instruction(OpCodeTypes::Ret, vec![]);
+            Statement::EnumStatement { .. } | Statement::StructStatement { .. } |
+            Statement::ExternStatement { .. } | Statement::ImportStatement { .. } => {}
+            Statement::MatchStatement { value, arms } => {
+                self.compile_match(value, arms);
             }
             Statement::ExpressionStatement(exp) => {
-                self.compile_expression(exp)
+                self.compile_expression(exp);
             }
         }
     }
-    pub fn add_builtin_function(&mut self, name: String) {
-        self.functions.insert(
-            name.clone(),
-            (vec![
-                Parameter{
-                    name: "fmt".to_string(),
-                    param_type: "string".to_string()
-                },
-                Parameter{
-                    name: "x".to_string(),
-                    param_type: "any".to_string()
+    // Compiles one module of a program into its own object. Its pub functions
+    // are exported and the declarations of the modules it imports are external.
+    pub fn compile_module(mut self, imports: Vec<Statement>) -> ir::Module {
+        self.imports = imports;
+        self.exports = true;
+        self.module.separate = true;
+        return self.compile();
+    }
+
+    fn declare_imports(&mut self) {
+        for stmt in self.imports.clone() {
+            if let Statement::FuncStatement { name, type_params, call_inputs, return_type, .. } = stmt {
+                if type_params.is_empty() {
+                    self.module.imports.push(name.clone());
+                    self.functions.insert(name, (call_inputs, return_type));
                 }
-            ], None)
-        );
-        self.new_instruction(OpCodeTypes::Func(name), vec![]);
-        self.print_builtin();
+            }
+        }
+    }
+
+    // Functions and extern functions can be used before their definition.
+    fn declare_functions(&mut self) {
+        for stmt in self.stmts.clone() {
+            match stmt {
+                Statement::FuncStatement { name, type_params, call_inputs, return_type, .. } if type_params.is_empty() => {
+                    self.functions.insert(name, (call_inputs, return_type));
+                }
+                Statement::ExternStatement { name, call_inputs, return_type, variadic, .. } => {
+                    self.declare_extern(name, call_inputs, return_type, variadic);
+                }
+                _ => {}
+            }
+        }
     }
 
-    pub fn compile(&mut self) {
-        self.new_instruction(OpCodeTypes::Global, vec![
-            String::from("main"),
-        ]);
-        self.new_instruction(OpCodeTypes::Extern, vec![
+    pub fn compile(mut self) -> ir::Module {
+        self.declare_extern(
             String::from("printf"),
-        ]);
-        self.add_builtin_function(String::from("print"));
+            vec![Parameter { name: String::from("fmt"), param_type: Type::String, span: Span::default() }],
+            Some(Type::Int),
+            true,
+        );
+        self.enums = collect_enums(self.imports.iter().chain(self.stmts.iter()));
+        self.collect_generics();
+        self.declare_imports();
+        self.declare_functions();
         for stmt in self.stmts.clone() {
             self.compile_stmt(stmt);
         }
+        self.compile_instances();
+        let lambdas = std::mem::take(&mut self.lambdas);
+        self.module.functions.extend(lambdas);
+        return self.module;
     }
 
 }
 
-
-impl std::fmt::Display for Instruction{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = self.operands.join(", ");
-        write!(f, "{} {}", format!("{}", self.opcode), s)
-    }
-}
-
-impl std::fmt::Display for Compiler {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "section .data\n")?;
-        if self.data_section.len() > 0 {
-            write!(f, "{}", self.data_section.iter()
-                .map(|inst| format!("{}", inst))
-                .collect::<Vec<String>>()
-                .join("\n")
-            )?;
-            write!(f, "\n")?;
+// @inline and @noinline, the lint makes sure they don't come together.
+fn inline_hint(attributes: &[Attribute]) -> Inline {
+    for attribute in attributes {
+        match attribute.name.as_str() {
+            "inline" => return Inline::Always,
+            "noinline" => return Inline::Never,
+            _ => {}
         }
-        write!(f, "{}", self.local_labels.iter()
-            .map(|inst| format!("{}", inst))
-            .collect::<Vec<String>>()
-            .join("\n")
-        )?;
-        write!(f, "\nsection .text\n")?;
-        write!(f, "{}", self.output.iter()
-            .map(|inst| format!("{}", inst))
-            .collect::<Vec<String>>()
-            .join("\n")
-        )
     }
+    return Inline::Auto;
 }
-
@@ -1,18 +1,21 @@
 use std::collections::HashMap;
 
+use crate::ast::Type;
+use crate::ir::VReg;
 
 
+
+// A variable lives in one register per slot of its type.
 #[derive(Clone)]
 pub struct Symbol {
-    pub symb_type: String,
-    pub offset: u64
+    pub symb_type: Type,
+    pub regs: Vec<VReg>,
 }
 
 #[derive(Clone)]
 pub struct SymbolTable {
     outer: Option<Box<SymbolTable>>,
     storage: HashMap<String, Box<Symbol>>,
-    pub cur_offset: u64
 }
 
 
@@ -21,8 +24,6 @@ impl SymbolTable {
         return SymbolTable{
             outer: None,
             storage: HashMap::new(),
-            cur_offset: 0
-
         }
     }
     pub fn move_out(&mut self) -> Self {
@@ -33,20 +34,19 @@ impl SymbolTable {
         return *table;
     }
     pub fn add(&mut self, name: String, s: Symbol) {
-        if self.cur_offset < s.offset {
-            self.cur_offset = s.offset;
-        }
         self.storage.insert(name, Box::new(s));
     }
     pub fn get(&self, name: String) -> Option<&Box<Symbol>> {
-        self.storage.get(&name)
+        match self.storage.get(&name) {
+            Some(s) => Some(s),
+            None => self.outer.as_ref()?.get(name),
+        }
     }
 
     pub fn new_from_outer(parent: SymbolTable) -> Self {
         return SymbolTable{
             outer: Some(Box::from(parent)),
             storage: HashMap::new(),
-            cur_offset: 0,
         }
     }
 }
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use crate::ast::Type;
+
+// Helpers for generic types, type parameters are Type::Named. They are
+// shared by the type checker and the compiler.
+
+// Structs are laid out like tuples of their fields in declaration order.
+pub struct StructDef {
+    pub type_params: Vec<String>,
+    pub fields: Vec<(String, Type)>,
+}
+
+// Names and types of the elements of a tuple, array or struct type.
+// Arrays are laid out like tuples of their elements.
+pub fn aggregate_fields(t: &Type, structs: &HashMap<String, StructDef>) -> Option<Vec<(String, Type)>> {
+    let (name, args) = match t {
+        Type::Tuple(elements) => {
+            return Some(elements.iter().enumerate().map(|(i, e)| (i.to_string(), e.clone())).collect());
+        }
+        Type::Array(element, len) => {
+            return Some((0..*len).map(|i| (i.to_string(), *element.clone())).collect());
+        }
+        Type::Named(name) => (name, Vec::new()),
+        Type::Generic { name, args } => (name, args.clone()),
+        _ => return None,
+    };
+    let def = structs.get(name)?;
+    let bindings: HashMap<String, Type> = def.type_params.iter().cloned().zip(args).collect();
+    return Some(def.fields.iter().map(|(n, f)| (n.clone(), substitute(f, &bindings))).collect());
+}
+
+// Replaces every type parameter in `t` by the type it is bound to.
+pub fn substitute(t: &Type, bindings: &HashMap<String, Type>) -> Type {
+    match t {
+        Type::Named(name) if bindings.contains_key(name) => bindings[name].clone(),
+        _ => t.map(|inner| substitute(inner, bindings)),
+    }
+}
+
+// Matches the type `pattern` containing the type parameters `params` against
+// `actual` and records the bindings, false if they can't match.
+pub fn unify(pattern: &Type, actual: &Type, params: &[String], bindings: &mut HashMap<String, Type>) -> bool {
+    if let Type::Named(name) = pattern {
+        if params.contains(name) {
+            match bindings.get(name) {
+                Some(bound) => return bound == actual || *actual == Type::Any,
+                None => {
+                    bindings.insert(name.clone(), actual.clone());
+                    return true;
+                }
+            }
+        }
+    }
+    let mut all = |p: &[Type], a: &[Type]| {
+        p.len() == a.len() && p.iter().zip(a).all(|(p, a)| unify(p, a, params, bindings))
+    };
+    match (pattern, actual) {
+        (_, Type::Any) => true,
+        (Type::Pointer(p), Type::Pointer(a)) => all(&[*p.clone()], &[*a.clone()]),
+        (Type::Array(p, n), Type::Array(a, m)) => n == m && all(&[*p.clone()], &[*a.clone()]),
+        (Type::Tuple(p), Type::Tuple(a)) => all(p, a),
+        (Type::Function { params: pp, return_type: pr }, Type::Function { params: ap, return_type: ar }) => {
+            all(&[*pr.clone()], &[*ar.clone()]) && all(pp, ap)
+        }
+        (Type::Generic { name: pn, args: pa }, Type::Generic { name: an, args: aa }) => pn == an && all(pa, aa),
+        _ => pattern == actual,
+    }
+}
+
+// Symbol name of an instance of a generic function, e.g. max<int> becomes max__int.
+pub fn mangle(name: &str, type_args: &[Type]) -> String {
+    let args: Vec<String> = type_args
+        .iter()
+        .map(|t| t.to_string().chars().filter(|c| *c != ' ').map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect())
+        .collect();
+    return format!("{}__{}", name, args.join("__"));
+}
@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+
+use crate::token::Span;
+
+// The files of a program, `Span::file` is an index into this list.
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub severity: Severity,
+    // The lint that reported it, shown as warning[unused_variables].
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, span: Span) -> Self {
+        return Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message,
+            span,
+        };
+    }
+
+    // error: message
+    //  --> file:line:col
+    //    |
+    //  3 |   var x: int = "s";
+    //    |                ^
+    pub fn render(&self, sources: &[SourceFile]) -> String {
+        let mut out = match self.code {
+            Some(code) => format!("{}[{}]: {}\n", self.severity, code, self.message),
+            None => format!("{}: {}\n", self.severity, self.message),
+        };
+        let file = match sources.get(self.span.file) {
+            Some(f) => f,
+            None => return out,
+        };
+        out += &format!(" --> {}:{}:{}\n", file.path.display(), self.span.line, self.span.col);
+        let line = match file.source.lines().nth(self.span.line.saturating_sub(1)) {
+            Some(l) => l,
+            None => return out,
+        };
+        let number = self.span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        out += &format!("{} |\n", gutter);
+        out += &format!("{} | {}\n", number, line);
+        // Tabs are kept so the caret lines up with the source line.
+        let indent: String = line
+            .chars()
+            .take(self.span.col.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        out += &format!("{} | {}^\n", gutter, indent);
+        return out;
+    }
+}
@@ -0,0 +1,381 @@
+use std::fmt;
+
+// Three-address code between the AST and the assembly. A function is a list of
+// basic blocks, each a list of instructions ending in one terminator. Values
+// live in virtual registers of one QWORD each, tuples and structs are split
+// into one register per slot. Registers can be assigned more than once, the
+// locals of a function are registers too.
+
+pub type VReg = usize;
+pub type BlockId = usize;
+
+// The scalar types a register can hold. Chars are kept zero extended in a
+// full QWORD, strings, pointers and closures are pointers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Ty {
+    Int,
+    Char,
+    Ptr,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Operand {
+    Reg(VReg),
+    Imm(i64),
+    // The address of a label, a string, closure object or function.
+    Label(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Xor,
+    // Comparisons give 0 or 1.
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+}
+
+// Helpers of the backend, they take their arguments like compiled functions.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Runtime {
+    // concat(left, right)
+    StrConcat,
+    // eq(a, b) gives 0 or 1
+    StrEq,
+    // slice(s, start, end), a negative end means the end of the string
+    StrSlice,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Callee {
+    Direct(String),
+    // A closure pointer, see compiler/closures.rs.
+    Indirect(Operand),
+    // A C function called with the System V convention.
+    Extern(String),
+    Runtime(Runtime),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Inst {
+    Copy { dest: VReg, src: Operand },
+    Binary { dest: VReg, op: BinOp, left: Operand, right: Operand },
+    // QWORD or zero extended BYTE at addr + offset.
+    Load { dest: VReg, addr: Operand, offset: i64, byte: bool },
+    // Stores a QWORD at addr + offset.
+    Store { addr: Operand, offset: i64, value: Operand },
+    // A value of n slots comes back in n registers.
+    Call { dests: Vec<VReg>, callee: Callee, args: Vec<Operand> },
+    // Only at the start of a block in SSA form, takes the value coming from
+    // the predecessor it was reached from.
+    Phi { dest: VReg, args: Vec<(BlockId, Operand)> },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Terminator {
+    Jump(BlockId),
+    // Goes to `then` if cond isn't 0.
+    Branch { cond: Operand, then: BlockId, otherwise: BlockId },
+    Return(Vec<Operand>),
+    // The end of a function that has to return a value.
+    Unreachable,
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub term: Terminator,
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<VReg>,
+    // Closures get the pointer to their closure object here.
+    pub env: Option<VReg>,
+    pub returns: Vec<Ty>,
+    // The type of every register, indexed by VReg.
+    pub regs: Vec<Ty>,
+    // Block 0 is the entry.
+    pub blocks: Vec<Block>,
+    // Visible to other modules when compiling them separately.
+    pub exported: bool,
+    pub inline: Inline,
+}
+
+// What @inline and @noinline ask of the inliner.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Inline {
+    // Left to the heuristic.
+    Auto,
+    Always,
+    Never,
+}
+
+// Read only data referenced by labels.
+#[derive(Clone, Debug)]
+pub enum Data {
+    String { label: String, value: String },
+    // A closure object without captures, [function].
+    Closure { label: String, function: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct Extern {
+    pub name: String,
+    pub variadic: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    pub data: Vec<Data>,
+    pub externs: Vec<Extern>,
+    // Functions of other modules called by this one.
+    pub imports: Vec<String>,
+    // A module compiled on its own has no main.
+    pub separate: bool,
+}
+
+impl Function {
+    pub fn new(name: String) -> Self {
+        return Function {
+            name,
+            params: Vec::new(),
+            env: None,
+            returns: Vec::new(),
+            regs: Vec::new(),
+            blocks: vec![Block { insts: Vec::new(), term: Terminator::Unreachable }],
+            exported: false,
+            inline: Inline::Auto,
+        };
+    }
+
+    pub fn new_reg(&mut self, ty: Ty) -> VReg {
+        self.regs.push(ty);
+        return self.regs.len() - 1;
+    }
+
+    pub fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Block { insts: Vec::new(), term: Terminator::Unreachable });
+        return self.blocks.len() - 1;
+    }
+}
+
+impl Inst {
+    // The registers the instruction writes.
+    pub fn defs(&self) -> Vec<VReg> {
+        match self {
+            Inst::Copy { dest, .. } | Inst::Binary { dest, .. } | Inst::Load { dest, .. } |
+            Inst::Phi { dest, .. } => vec![*dest],
+            Inst::Call { dests, .. } => dests.clone(),
+            Inst::Store { .. } => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Inst::Copy { src, .. } => vec![src],
+            Inst::Binary { left, right, .. } => vec![left, right],
+            Inst::Load { addr, .. } => vec![addr],
+            Inst::Store { addr, value, .. } => vec![addr, value],
+            Inst::Phi { args, .. } => args.iter().map(|(_, a)| a).collect(),
+            Inst::Call { callee, args, .. } => {
+                let mut ops: Vec<&Operand> = args.iter().collect();
+                if let Callee::Indirect(c) = callee {
+                    ops.push(c);
+                }
+                ops
+            }
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Inst::Copy { src, .. } => vec![src],
+            Inst::Binary { left, right, .. } => vec![left, right],
+            Inst::Load { addr, .. } => vec![addr],
+            Inst::Store { addr, value, .. } => vec![addr, value],
+            Inst::Phi { args, .. } => args.iter_mut().map(|(_, a)| a).collect(),
+            Inst::Call { callee, args, .. } => {
+                let mut ops: Vec<&mut Operand> = args.iter_mut().collect();
+                if let Callee::Indirect(c) = callee {
+                    ops.push(c);
+                }
+                ops
+            }
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(b) => vec![*b],
+            Terminator::Branch { then, otherwise, .. } => vec![*then, *otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Return(values) => values.iter().collect(),
+            Terminator::Jump(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Return(values) => values.iter_mut().collect(),
+            Terminator::Jump(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Int => write!(f, "int"),
+            Ty::Char => write!(f, "char"),
+            Ty::Ptr => write!(f, "ptr"),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Reg(r) => write!(f, "%{}", r),
+            Operand::Imm(i) => write!(f, "{}", i),
+            Operand::Label(l) => write!(f, "@{}", l),
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Runtime::StrConcat => write!(f, "__str_concat"),
+            Runtime::StrEq => write!(f, "__str_eq"),
+            Runtime::StrSlice => write!(f, "__str_slice"),
+        }
+    }
+}
+
+fn join<T: fmt::Display>(items: &[T]) -> String {
+    return items.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ");
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inst::Copy { dest, src } => write!(f, "%{} = {}", dest, src),
+            Inst::Binary { dest, op, left, right } => write!(f, "%{} = {} {}, {}", dest, op, left, right),
+            Inst::Load { dest, addr, offset, byte } => {
+                let width = if *byte { "byte" } else { "qword" };
+                write!(f, "%{} = load {} [{} + {}]", dest, width, addr, offset)
+            }
+            Inst::Store { addr, offset, value } => write!(f, "store [{} + {}], {}", addr, offset, value),
+            Inst::Call { dests, callee, args } => {
+                if !dests.is_empty() {
+                    let dests: Vec<String> = dests.iter().map(|d| format!("%{}", d)).collect();
+                    write!(f, "{} = ", dests.join(", "))?;
+                }
+                match callee {
+                    Callee::Direct(name) => write!(f, "call {}", name)?,
+                    Callee::Indirect(closure) => write!(f, "call [{}]", closure)?,
+                    Callee::Extern(name) => write!(f, "call extern {}", name)?,
+                    Callee::Runtime(r) => write!(f, "call {}", r)?,
+                }
+                write!(f, "({})", join(args))
+            }
+            Inst::Phi { dest, args } => {
+                let args: Vec<String> = args.iter().map(|(b, a)| format!("[.B{}: {}]", b, a)).collect();
+                write!(f, "%{} = phi {}", dest, args.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(b) => write!(f, "jump .B{}", b),
+            Terminator::Branch { cond, then, otherwise } => {
+                write!(f, "branch {}, .B{}, .B{}", cond, then, otherwise)
+            }
+            Terminator::Return(values) if values.is_empty() => write!(f, "ret"),
+            Terminator::Return(values) => write!(f, "ret {}", join(values)),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+// func max(%0: int, %1: int) -> int {
+// .B0:
+//     %2 = gt %0, %1
+//     branch %2, .B1, .B2
+// ...
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|p| format!("%{}: {}", p, self.regs[*p])).collect();
+        match self.inline {
+            Inline::Auto => {}
+            Inline::Always => write!(f, "inline ")?,
+            Inline::Never => write!(f, "noinline ")?,
+        }
+        write!(f, "func {}({})", self.name, params.join(", "))?;
+        if let Some(env) = self.env {
+            write!(f, " env %{}", env)?;
+        }
+        if !self.returns.is_empty() {
+            write!(f, " -> {}", join(&self.returns))?;
+        }
+        writeln!(f, " {{")?;
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, ".B{}:", id)?;
+            for inst in &block.insts {
+                writeln!(f, "    {}", inst)?;
+            }
+            writeln!(f, "    {}", block.term)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for e in &self.externs {
+            writeln!(f, "extern {}{}", e.name, if e.variadic { "(...)" } else { "" })?;
+        }
+        for name in &self.imports {
+            writeln!(f, "import {}", name)?;
+        }
+        for data in &self.data {
+            match data {
+                Data::String { label, value } => writeln!(f, "data {} = \"{}\"", label, value.escape_default())?,
+                Data::Closure { label, function } => writeln!(f, "data {} = closure {}", label, function)?,
+            }
+        }
+        for function in &self.functions {
+            writeln!(f)?;
+            writeln!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
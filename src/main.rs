@@ -4,23 +4,119 @@ mod ast;
 mod parser;
 mod server;
 mod optimizer;
+mod semantic;
+mod modules;
+mod diagnostics;
 mod compiler;
+mod ir;
+mod backend;
 
 
 use std::env;
+use std::path::PathBuf;
 use crate::compiler::*;
+use crate::backend::{Backend, Peephole};
 
 use actix_web::web;
 use actix_web::*;
 use crate::server::*;
 
-use crate::tokenizer::Tokenizer;
-use crate::parser::*;
+use crate::modules::*;
+use crate::diagnostics::*;
+use crate::semantic::{Level, LintLevels};
+
+// compiler                 compiles test.test to test.asm
+// compiler serve           starts the web interface
+// compiler <file> [-I <dir>]... [-o <out.asm>] [--separate] [--emit ir|asm]
+//          [--no-peephole <rule>]... [-A|-W|-D <lint>]...
+//
+// --emit ir writes the IR of the program to <file>.ir instead of assembly.
+// --no-peephole <rule> turns off one rewrite of the peephole optimizer, or all
+// of them: push-pop, redundant-load, push-operand, jump-to-next, zero-xor.
+// -A, -W and -D allow a lint, make it warn or turn it into an error, the
+// last one given wins. -D warnings denies every lint.
+// With --separate every module gets its own <module>.asm next to the output
+// that can be assembled on its own and linked with the others.
+struct Options {
+    input: PathBuf,
+    output: PathBuf,
+    search_paths: Vec<PathBuf>,
+    separate: bool,
+    emit_ir: bool,
+    peephole: Peephole,
+    lints: LintLevels,
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut options = Options {
+        input: PathBuf::from("test.test"),
+        output: PathBuf::new(),
+        search_paths: Vec::new(),
+        separate: false,
+        emit_ir: false,
+        peephole: Peephole::new(),
+        lints: LintLevels::new(),
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-I" => options.search_paths.push(PathBuf::from(iter.next().expect("-I needs a directory"))),
+            "-o" => options.output = PathBuf::from(iter.next().expect("-o needs a file")),
+            "--separate" => options.separate = true,
+            "--emit" => match iter.next().map(|e| e.as_str()) {
+                Some("ir") => options.emit_ir = true,
+                Some("asm") => options.emit_ir = false,
+                e => panic!("--emit needs ir or asm, not {:?}", e),
+            },
+            "--no-peephole" => {
+                let rule = iter.next().expect("--no-peephole needs a rule");
+                if !options.peephole.disable(rule) {
+                    panic!("Unknown peephole rule {}", rule);
+                }
+            }
+            "-A" | "-W" | "-D" => {
+                let level = match arg.as_str() {
+                    "-A" => Level::Allow,
+                    "-W" => Level::Warn,
+                    _ => Level::Deny,
+                };
+                let lint = iter.next().unwrap_or_else(|| panic!("{} needs a lint", arg));
+                if !options.lints.set(lint, level) {
+                    panic!("Unknown lint {}", lint);
+                }
+            }
+            _ => options.input = PathBuf::from(arg),
+        }
+    }
+    if options.output.as_os_str().is_empty() {
+        options.output = options.input.with_extension(if options.emit_ir { "ir" } else { "asm" });
+    }
+    return options;
+}
+
+// Prints the diagnostics and stops if any of them is an error.
+fn report(diagnostics: Vec<Diagnostic>, sources: &[SourceFile]) {
+    for d in &diagnostics {
+        eprint!("{}", d.render(sources));
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+}
+
+fn emit(module: &ir::Module, options: &Options) -> String {
+    if options.emit_ir {
+        return module.to_string();
+    }
+    let mut backend = Backend::new(options.peephole.clone());
+    backend.compile(module);
+    return format!("{}\n", backend);
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
+    if args.get(1).is_some_and(|a| a == "serve") {
         HttpServer::new(|| {
             App::new()
                 .route("/parse", web::post().to(parse))
@@ -31,20 +127,39 @@ async fn main() -> std::io::Result<()> {
         .run()
         .await
     } else {
-        let s = std::fs::read_to_string("test.test").unwrap();
-        let t = Tokenizer::new(s);
-
-        let mut p = Parser::new(t);
-        let stmt = p.parse_program();
-        for i in stmt.clone() {
-            p.print_stmt(i);
+        let options = parse_args(&args[1..]);
+        let loaded = ModuleLoader::new(options.search_paths.clone()).load(&options.input);
+        let (mut modules, mut program) = (loaded.modules, loaded.program);
+        let all: Vec<_> = modules.iter().flat_map(|m| m.stmts.iter()).collect();
+        let errors = semantic::resolve_names(&all, &mut program, &loaded.spans);
+        report(errors.iter().map(|e| e.diagnostic()).collect(), &loaded.sources);
+        let all: Vec<_> = modules.iter_mut().flat_map(|m| m.stmts.iter_mut()).collect();
+        let (errors, types) = semantic::check_types(all, &mut program, &loaded.spans);
+        // Flow analysis relies on matches being exhaustive, so it only sees programs that type check.
+        report(errors.iter().map(|e| e.diagnostic()).collect(), &loaded.sources);
+        let all: Vec<_> = modules.iter().flat_map(|m| m.stmts.iter()).collect();
+        let mut diagnostics = semantic::check_flow(&all, &program, &loaded.spans, &options.lints);
+        diagnostics.extend(semantic::check_lints(&all, &program, &loaded.spans, &options.lints));
+        report(diagnostics, &loaded.sources);
+        let stmts: Vec<_> = modules.iter().flat_map(|m| m.stmts.clone()).collect();
+        if !options.separate {
+            let mut module = Compiler::new(stmts, program, types).compile();
+            optimizer::optimize(&mut module);
+            std::fs::write(&options.output, emit(&module, &options))?;
+            return Ok(());
+        }
+        let extension = if options.emit_ir { "ir" } else { "asm" };
+        for (i, module) in modules.iter().enumerate() {
+            let imports = modules[..i].iter().flat_map(|m| m.stmts.clone()).collect();
+            let mut ir = Compiler::new(module.stmts.clone(), program.clone(), types.clone()).compile_module(imports);
+            optimizer::optimize(&mut ir);
+            let output = if module.name.is_empty() {
+                options.output.clone()
+            } else {
+                options.output.with_file_name(format!("{}.{}", module.name, extension))
+            };
+            std::fs::write(output, emit(&ir, &options))?;
         }
-        let mut c = Compiler::new(stmt, p.get_program());
-        c.compile();
-        //println!("{}", c);
-        std::fs::write("test.asm", format!("{}\n", c))?;
         Ok(())
     }
 }
-
-
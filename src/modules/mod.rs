@@ -0,0 +1,396 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::ast::*;
+use crate::diagnostics::SourceFile;
+use crate::parser::*;
+use crate::token::Span;
+use crate::tokenizer::*;
+
+// A source file of the program. Everything a module declares at the top level
+// is renamed to `<module>.<name>`, so `max` in util.test becomes `util.max` and
+// the modules can be compiled together without their names clashing. The
+// root file keeps its names, main has to stay main.
+pub struct Module {
+    pub name: String,
+    pub file: PathBuf,
+    pub stmts: Vec<Statement>,
+    // Top level declarations and whether they are pub.
+    pub declarations: HashMap<String, bool>,
+}
+
+// Every module of a program, their expressions share one arena.
+pub struct LoadedProgram {
+    pub modules: Vec<Module>,
+    pub program: Program,
+    pub spans: Vec<Span>,
+    pub sources: Vec<SourceFile>,
+}
+
+pub struct ModuleLoader {
+    search_paths: Vec<PathBuf>,
+    program: Program,
+    spans: Vec<Span>,
+    sources: Vec<SourceFile>,
+    // Loaded modules, every module comes after the modules it imports.
+    modules: Vec<Module>,
+    // Modules that are being loaded right now, used to detect import cycles.
+    loading: Vec<(PathBuf, String)>,
+}
+
+impl ModuleLoader {
+    pub fn new(search_paths: Vec<PathBuf>) -> Self {
+        return ModuleLoader {
+            search_paths,
+            program: Vec::new(),
+            spans: Vec::new(),
+            sources: Vec::new(),
+            modules: Vec::new(),
+            loading: Vec::new(),
+        };
+    }
+
+    // Loads the root file and every module it reaches through imports.
+    // The directory of the root file is searched after the search paths.
+    pub fn load(mut self, root: &Path) -> LoadedProgram {
+        let root_dir = root.parent().unwrap_or(Path::new("")).to_path_buf();
+        self.search_paths.push(root_dir);
+        self.load_module(root.to_path_buf(), String::new());
+        return LoadedProgram {
+            modules: self.modules,
+            program: self.program,
+            spans: self.spans,
+            sources: self.sources,
+        };
+    }
+
+    fn load_module(&mut self, file: PathBuf, name: String) -> usize {
+        let canonical = file
+            .canonicalize()
+            .unwrap_or_else(|_| panic!("Can't open {}", file.display()));
+        if let Some(start) = self.loading.iter().position(|(f, _)| *f == canonical) {
+            let mut cycle: Vec<String> = self.loading[start..].iter().map(|(_, n)| n.clone()).collect();
+            cycle.push(name);
+            panic!("Import cycle: {}", cycle.join(" -> "));
+        }
+        if let Some(idx) = self.modules.iter().position(|m| m.file == canonical) {
+            return idx;
+        }
+        if self.modules.iter().any(|m| m.name == name) {
+            panic!("Two different files are imported as module {}", name);
+        }
+
+        let source = std::fs::read_to_string(&canonical)
+            .unwrap_or_else(|_| panic!("Can't read {}", file.display()));
+        let file_id = self.sources.len();
+        self.sources.push(SourceFile {
+            path: file.clone(),
+            source: source.clone(),
+        });
+        let mut p = Parser::with_program(
+            Tokenizer::with_file(source, file_id),
+            std::mem::take(&mut self.program),
+            std::mem::take(&mut self.spans),
+        );
+        let mut stmts = p.parse_program();
+        self.program = p.get_program();
+        self.spans = p.get_spans();
+
+        let shown = if name.is_empty() { file.display().to_string() } else { name.clone() };
+        self.loading.push((canonical.clone(), shown));
+        let mut imports: HashMap<String, usize> = HashMap::new();
+        for stmt in &stmts {
+            if let Statement::ImportStatement { path } = stmt {
+                let import_file = self.resolve(&canonical, path);
+                let idx = self.load_module(import_file, path.join("."));
+                let alias = path.last().unwrap().clone();
+                if imports.insert(alias.clone(), idx).is_some_and(|other| other != idx) {
+                    panic!("Two imports of {} are called {}", file.display(), alias);
+                }
+            }
+        }
+        self.loading.pop();
+
+        let declarations = declarations(&stmts);
+        let mut namespace = Namespace {
+            module: &name,
+            declarations: &declarations,
+            imports: imports
+                .into_iter()
+                .map(|(alias, idx)| (alias, &self.modules[idx]))
+                .collect(),
+            scopes: Vec::new(),
+            type_params: Vec::new(),
+            program: &mut self.program,
+        };
+        for stmt in stmts.iter_mut() {
+            namespace.top_level(stmt);
+        }
+        self.modules.push(Module {
+            name,
+            file: canonical,
+            stmts,
+            declarations,
+        });
+        return self.modules.len() - 1;
+    }
+
+    // util.math is looked up as util/math.<ext> next to the importing file first,
+    // then in every search path. The extension is the one of the importing file.
+    fn resolve(&self, importer: &Path, path: &Vec<String>) -> PathBuf {
+        let mut relative: PathBuf = path.iter().collect();
+        if let Some(ext) = importer.extension() {
+            relative.set_extension(ext);
+        }
+        let dir = importer.parent().unwrap_or(Path::new(""));
+        std::iter::once(dir.to_path_buf())
+            .chain(self.search_paths.iter().cloned())
+            .map(|d| d.join(&relative))
+            .find(|f| f.is_file())
+            .unwrap_or_else(|| panic!("Can't find module {} imported by {}", path.join("."), importer.display()))
+    }
+}
+
+fn declarations(stmts: &Vec<Statement>) -> HashMap<String, bool> {
+    let mut declarations = HashMap::new();
+    for stmt in stmts {
+        let (name, public) = match stmt {
+            Statement::FuncStatement { name, public, .. } |
+            Statement::EnumStatement { name, public, .. } |
+            Statement::StructStatement { name, public, .. } => (name, *public),
+            _ => continue,
+        };
+        // Duplicates are reported by the resolver.
+        declarations.entry(name.clone()).or_insert(public);
+    }
+    return declarations;
+}
+
+// Rewrites the names used in one module to the qualified names of the
+// declarations they refer to.
+struct Namespace<'a> {
+    module: &'a String,
+    declarations: &'a HashMap<String, bool>,
+    imports: HashMap<String, &'a Module>,
+    // Local variables shadow declarations and imports.
+    scopes: Vec<HashSet<String>>,
+    type_params: Vec<String>,
+    program: &'a mut Program,
+}
+
+impl<'a> Namespace<'a> {
+    fn qualify(&self, name: &String) -> String {
+        if self.module.is_empty() {
+            return name.clone();
+        }
+        return format!("{}.{}", self.module, name);
+    }
+
+    fn is_local(&self, name: &String) -> bool {
+        return self.scopes.iter().any(|s| s.contains(name));
+    }
+
+    fn declare(&mut self, name: &String) {
+        self.scopes.last_mut().unwrap().insert(name.clone());
+    }
+
+    fn is_import(&self, name: &String) -> bool {
+        return self.imports.contains_key(name) && !self.is_local(name);
+    }
+
+    // `member` of the imported module `alias`.
+    fn member(&self, alias: &String, member: &String) -> String {
+        let module = self.imports[alias];
+        match module.declarations.get(member) {
+            Some(true) => format!("{}.{}", module.name, member),
+            Some(false) => panic!("{} is private to module {}", member, module.name),
+            None => panic!("Module {} has no {}", module.name, member),
+        }
+    }
+
+    // Qualified name of an unqualified or `module.name` reference.
+    fn resolve(&self, name: &String) -> String {
+        if let Some((alias, member)) = name.split_once('.') {
+            if self.is_import(&alias.to_string()) {
+                return self.member(&alias.to_string(), &member.to_string());
+            }
+            return name.clone();
+        }
+        if !self.is_local(name) && self.declarations.contains_key(name) {
+            return self.qualify(name);
+        }
+        return name.clone();
+    }
+
+    fn resolve_type(&self, t: &Type) -> Type {
+        match t {
+            Type::Named(name) if !self.type_params.contains(name) => Type::Named(self.resolve(name)),
+            Type::Generic { name, args } => Type::Generic {
+                name: self.resolve(name),
+                args: args.iter().map(|a| self.resolve_type(a)).collect(),
+            },
+            _ => t.map(|inner| self.resolve_type(inner)),
+        }
+    }
+
+    fn top_level(&mut self, stmt: &mut Statement) {
+        self.scopes.push(HashSet::new());
+        match stmt {
+            Statement::FuncStatement { name, .. } |
+            Statement::EnumStatement { name, .. } |
+            Statement::StructStatement { name, .. } => {
+                *name = self.qualify(name);
+            }
+            _ => {}
+        }
+        self.stmt(stmt);
+        self.scopes.pop();
+    }
+
+    fn block(&mut self, body: &mut Vec<Box<Statement>>) {
+        self.scopes.push(HashSet::new());
+        for stmt in body.iter_mut() {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn function(&mut self, call_inputs: &mut Vec<Parameter>, return_type: &mut Option<Type>, body: &mut Vec<Box<Statement>>) {
+        self.scopes.push(HashSet::new());
+        for param in call_inputs.iter_mut() {
+            param.param_type = self.resolve_type(&param.param_type);
+            self.declare(&param.name);
+        }
+        if let Some(r) = return_type {
+            *r = self.resolve_type(r);
+        }
+        self.block(body);
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::IfElseStatement { condition, if_body, else_body } => {
+                self.exp(*condition);
+                self.block(if_body);
+                if let Some(else_body) = else_body {
+                    self.block(else_body);
+                }
+            }
+            Statement::FuncStatement { type_params, call_inputs, return_type, body, .. } => {
+                let outer = std::mem::replace(&mut self.type_params, type_params.clone());
+                self.function(call_inputs, return_type, body);
+                self.type_params = outer;
+            }
+            Statement::StructStatement { type_params, fields, .. } => {
+                let outer = std::mem::replace(&mut self.type_params, type_params.clone());
+                for field in fields.iter_mut() {
+                    field.param_type = self.resolve_type(&field.param_type);
+                }
+                self.type_params = outer;
+            }
+            Statement::VarStatement { name, value, var_type, .. } => {
+                if let Some(v) = value {
+                    self.exp(*v);
+                }
+                if let Some(t) = var_type {
+                    *t = self.resolve_type(t);
+                }
+                self.declare(name);
+            }
+            Statement::DestructureStatement { names, value, .. } => {
+                self.exp(*value);
+                for name in names.iter() {
+                    self.declare(name);
+                }
+            }
+            Statement::MatchStatement { value, arms } => {
+                self.exp(*value);
+                for arm in arms.iter_mut() {
+                    if let Pattern::Variant { enum_name, .. } = &mut arm.pattern {
+                        *enum_name = self.resolve(enum_name);
+                    }
+                    self.block(&mut arm.body);
+                }
+            }
+            Statement::ReturnStatement { value: Some(value), .. } | Statement::ExpressionStatement(value) => {
+                self.exp(*value);
+            }
+            Statement::ReturnStatement { value: None, .. } => {}
+            Statement::ExternStatement { call_inputs, return_type, .. } => {
+                for param in call_inputs.iter_mut() {
+                    param.param_type = self.resolve_type(&param.param_type);
+                }
+                if let Some(r) = return_type {
+                    *r = self.resolve_type(r);
+                }
+            }
+            Statement::EnumStatement { .. } | Statement::ImportStatement { .. } => {}
+        }
+    }
+
+    fn exp(&mut self, exp: ExpRef) {
+        match *self.program[exp].clone() {
+            Expression::Identifier { value, ident_type, decl } => {
+                self.program[exp] = Box::new(Expression::Identifier {
+                    value: self.resolve(&value),
+                    ident_type,
+                    decl,
+                });
+            }
+            Expression::GenericInstance { value, type_args } => {
+                self.program[exp] = Box::new(Expression::GenericInstance {
+                    value: self.resolve(&value),
+                    type_args: type_args.iter().map(|t| self.resolve_type(t)).collect(),
+                });
+            }
+            Expression::FieldAccess { left, field } => {
+                if let Expression::Identifier { value, ident_type, decl } = &*self.program[left] {
+                    if self.is_import(value) {
+                        self.program[exp] = Box::new(Expression::Identifier {
+                            value: self.member(value, &field),
+                            ident_type: ident_type.clone(),
+                            decl: *decl,
+                        });
+                        return;
+                    }
+                }
+                self.exp(left);
+            }
+            Expression::FunctionCall { left, parameters } => {
+                self.exp(left);
+                for p in parameters {
+                    self.exp(p);
+                }
+            }
+            Expression::InfixExpression { left, right, .. } | Expression::AssignExpression { left, right } |
+            Expression::Index { left, index: right } => {
+                self.exp(left);
+                self.exp(right);
+            }
+            Expression::PrefixExpression { right, .. } => {
+                self.exp(right);
+            }
+            Expression::Slice { left, start, end } => {
+                self.exp(left);
+                for e in start.into_iter().chain(end) {
+                    self.exp(e);
+                }
+            }
+            Expression::Tuple(elements) => {
+                for e in elements {
+                    self.exp(e);
+                }
+            }
+            Expression::Lambda { mut call_inputs, mut return_type, mut body } => {
+                self.function(&mut call_inputs, &mut return_type, &mut body);
+                self.program[exp] = Box::new(Expression::Lambda {
+                    call_inputs,
+                    return_type,
+                    body,
+                });
+            }
+            Expression::Integer(_) | Expression::Char(_) | Expression::String(_) => {}
+        }
+    }
+}
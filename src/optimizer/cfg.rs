@@ -0,0 +1,39 @@
+use crate::ir::{BlockId, Function};
+
+// The control flow graph of a function. Blocks that can't be reached from the
+// entry are left out of `order` and have no predecessors recorded.
+pub struct Cfg {
+    pub succs: Vec<Vec<BlockId>>,
+    pub preds: Vec<Vec<BlockId>>,
+    // Reachable blocks in reverse postorder, the entry first.
+    pub order: Vec<BlockId>,
+}
+
+impl Cfg {
+    pub fn new(function: &Function) -> Self {
+        let succs: Vec<Vec<BlockId>> = function.blocks.iter().map(|b| b.term.successors()).collect();
+        let mut visited = vec![false; succs.len()];
+        let mut postorder = Vec::new();
+        postorder_from(0, &succs, &mut visited, &mut postorder);
+        let mut preds = vec![Vec::new(); succs.len()];
+        for b in &postorder {
+            for s in &succs[*b] {
+                if !preds[*s].contains(b) {
+                    preds[*s].push(*b);
+                }
+            }
+        }
+        postorder.reverse();
+        return Cfg { succs, preds, order: postorder };
+    }
+}
+
+fn postorder_from(block: BlockId, succs: &[Vec<BlockId>], visited: &mut [bool], out: &mut Vec<BlockId>) {
+    visited[block] = true;
+    for s in &succs[block] {
+        if !visited[*s] {
+            postorder_from(*s, succs, visited, out);
+        }
+    }
+    out.push(block);
+}
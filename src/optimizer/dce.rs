@@ -0,0 +1,139 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{Callee, Data, Function, Inst, Module, Operand, Terminator, VReg};
+use super::cfg::Cfg;
+
+// Removes instructions without side effects whose result is never read,
+// until there are none left. Calls stay, they might print or allocate.
+pub fn eliminate_dead_code(function: &mut Function) {
+    loop {
+        let mut used: HashSet<VReg> = HashSet::new();
+        for block in &function.blocks {
+            let ops = block.insts.iter().flat_map(|i| i.operands()).chain(block.term.operands());
+            for op in ops {
+                if let Operand::Reg(r) = op {
+                    used.insert(*r);
+                }
+            }
+        }
+        let mut changed = false;
+        for block in &mut function.blocks {
+            let before = block.insts.len();
+            block.insts.retain(|inst| match inst {
+                Inst::Copy { dest, .. } | Inst::Binary { dest, .. } | Inst::Load { dest, .. } |
+                Inst::Phi { dest, .. } => used.contains(dest),
+                Inst::Store { .. } | Inst::Call { .. } => true,
+            });
+            changed |= block.insts.len() != before;
+        }
+        if !changed {
+            return;
+        }
+    }
+}
+
+// Appends blocks to their only predecessor when it jumps straight to them,
+// which leaves them unreachable. Chains of jumps left by folded ifs collapse.
+pub fn merge_blocks(function: &mut Function) {
+    let cfg = Cfg::new(function);
+    for b in cfg.order.iter().copied() {
+        while let Terminator::Jump(s) = function.blocks[b].term {
+            if s == 0 || s == b || cfg.preds[s].len() != 1 {
+                break;
+            }
+            let next = std::mem::replace(&mut function.blocks[s].term, Terminator::Unreachable);
+            let insts = std::mem::take(&mut function.blocks[s].insts);
+            function.blocks[b].insts.extend(insts);
+            function.blocks[b].term = next;
+        }
+    }
+}
+
+// Drops the blocks that can't be reached from the entry, like the code after
+// a return or the branch of an if that is never taken, and numbers the rest
+// in their old order. The function must not be in SSA form.
+pub fn remove_unreachable_blocks(function: &mut Function) {
+    let cfg = Cfg::new(function);
+    let mut reachable: Vec<usize> = cfg.order.clone();
+    reachable.sort();
+    let new_ids: HashMap<usize, usize> = reachable.iter().enumerate().map(|(new, old)| (*old, new)).collect();
+    let blocks = std::mem::take(&mut function.blocks);
+    for (id, mut block) in blocks.into_iter().enumerate() {
+        if !new_ids.contains_key(&id) {
+            continue;
+        }
+        match &mut block.term {
+            Terminator::Jump(b) => *b = new_ids[b],
+            Terminator::Branch { then, otherwise, .. } => {
+                *then = new_ids[then];
+                *otherwise = new_ids[otherwise];
+            }
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+        function.blocks.push(block);
+    }
+}
+
+// Tree shaking: keeps only the functions main can reach through calls or
+// function values, and the data and externs they use. Modules compiled on
+// their own keep everything they export.
+pub fn remove_unused_functions(module: &mut Module) {
+    let closures: HashMap<&String, &String> = module.data
+        .iter()
+        .filter_map(|d| match d {
+            Data::Closure { label, function } => Some((label, function)),
+            Data::String { .. } => None,
+        })
+        .collect();
+    let by_name: HashMap<&String, &Function> = module.functions.iter().map(|f| (&f.name, f)).collect();
+    let mut work: Vec<&String> = module.functions
+        .iter()
+        .filter(|f| if module.separate { f.exported } else { f.name == "main" })
+        .map(|f| &f.name)
+        .collect();
+    if work.is_empty() {
+        return;
+    }
+    let mut kept: HashSet<&String> = HashSet::new();
+    let mut labels: HashSet<&String> = HashSet::new();
+    let mut called: HashSet<&String> = HashSet::new();
+    while let Some(name) = work.pop() {
+        if !kept.insert(name) {
+            continue;
+        }
+        let function = match by_name.get(name) {
+            Some(f) => f,
+            // Functions of other modules.
+            None => continue,
+        };
+        for block in &function.blocks {
+            for inst in &block.insts {
+                if let Inst::Call { callee: Callee::Direct(n) | Callee::Extern(n), .. } = inst {
+                    called.insert(n);
+                    work.push(n);
+                }
+                for op in inst.operands() {
+                    if let Operand::Label(l) = op {
+                        labels.insert(l);
+                        work.push(closures.get(l).copied().unwrap_or(l));
+                    }
+                }
+            }
+            for op in block.term.operands() {
+                if let Operand::Label(l) = op {
+                    labels.insert(l);
+                    work.push(closures.get(l).copied().unwrap_or(l));
+                }
+            }
+        }
+    }
+    let kept: HashSet<String> = kept.into_iter().cloned().collect();
+    let labels: HashSet<String> = labels.into_iter().cloned().collect();
+    let called: HashSet<String> = called.into_iter().cloned().collect();
+    module.functions.retain(|f| kept.contains(&f.name));
+    module.data.retain(|d| match d {
+        Data::String { label, .. } | Data::Closure { label, .. } => labels.contains(label),
+    });
+    module.externs.retain(|e| called.contains(&e.name));
+    module.imports.retain(|i| kept.contains(i));
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeSet;
+
+use crate::ir::BlockId;
+use super::cfg::Cfg;
+
+// Dominator tree and dominance frontiers of the reachable blocks, computed
+// with the iterative algorithm of Cooper, Harvey and Kennedy.
+pub struct Dominators {
+    // The blocks every block immediately dominates.
+    pub children: Vec<Vec<BlockId>>,
+    pub frontiers: Vec<BTreeSet<BlockId>>,
+}
+
+impl Dominators {
+    pub fn new(cfg: &Cfg) -> Self {
+        let n = cfg.succs.len();
+        let mut rpo_index = vec![usize::MAX; n];
+        for (i, b) in cfg.order.iter().enumerate() {
+            rpo_index[*b] = i;
+        }
+        let mut idom: Vec<Option<BlockId>> = vec![None; n];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in cfg.order.iter().skip(1) {
+                let mut new_idom = None;
+                for p in &cfg.preds[*b] {
+                    if idom[*p].is_none() {
+                        continue;
+                    }
+                    new_idom = match new_idom {
+                        None => Some(*p),
+                        Some(other) => Some(intersect(*p, other, &idom, &rpo_index)),
+                    };
+                }
+                if new_idom.is_some() && idom[*b] != new_idom {
+                    idom[*b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+
+        let mut children = vec![Vec::new(); n];
+        for b in &cfg.order {
+            if let Some(d) = idom[*b] {
+                children[d].push(*b);
+            }
+        }
+        let mut frontiers = vec![BTreeSet::new(); n];
+        for b in &cfg.order {
+            if cfg.preds[*b].len() < 2 {
+                continue;
+            }
+            for p in &cfg.preds[*b] {
+                let mut runner = *p;
+                while Some(runner) != idom[*b] {
+                    frontiers[runner].insert(*b);
+                    match idom[runner] {
+                        Some(d) => runner = d,
+                        None => break,
+                    }
+                }
+            }
+        }
+        return Dominators { children, frontiers };
+    }
+}
+
+fn intersect(a: BlockId, b: BlockId, idom: &[Option<BlockId>], rpo_index: &[usize]) -> BlockId {
+    let (mut a, mut b) = (a, b);
+    while a != b {
+        while rpo_index[a] > rpo_index[b] {
+            a = idom[a].unwrap();
+        }
+        while rpo_index[b] > rpo_index[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    return a;
+}
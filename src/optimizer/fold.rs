@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use crate::ir::{BinOp, Function, Inst, Operand, Terminator, VReg};
+use super::cfg::Cfg;
+
+// Constant folding and algebraic simplification on a function in SSA form.
+// Registers that are copies of a constant, label or other register are
+// replaced by it, operations on constants are evaluated with the wrapping
+// arithmetic of the target and branches on constants become jumps. The
+// copies that are left over are removed by dead code elimination.
+pub fn fold_constants(function: &mut Function) {
+    loop {
+        let cfg = Cfg::new(function);
+        let mut changed = false;
+
+        // Phis keep only the values of edges that can still be taken.
+        for b in &cfg.order {
+            for inst in &mut function.blocks[*b].insts {
+                if let Inst::Phi { args, .. } = inst {
+                    let before = args.len();
+                    args.retain(|(p, _)| cfg.preds[*b].contains(p));
+                    changed |= args.len() != before;
+                }
+            }
+        }
+
+        let mut values: HashMap<VReg, Operand> = HashMap::new();
+        for b in &cfg.order {
+            for inst in &function.blocks[*b].insts {
+                match inst {
+                    Inst::Copy { dest, src } if *src != Operand::Reg(*dest) => {
+                        values.insert(*dest, src.clone());
+                    }
+                    // A phi that gets the same value on every edge is that value.
+                    Inst::Phi { dest, args } if !args.is_empty() && args.iter().all(|(_, a)| *a == args[0].1 && *a != Operand::Reg(*dest)) => {
+                        values.insert(*dest, args[0].1.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for b in &cfg.order {
+            let block = &mut function.blocks[*b];
+            for inst in &mut block.insts {
+                for op in inst.operands_mut() {
+                    changed |= replace(op, &values);
+                }
+                if let Some(simple) = simplify(inst) {
+                    *inst = simple;
+                    changed = true;
+                }
+            }
+            for op in block.term.operands_mut() {
+                changed |= replace(op, &values);
+            }
+            if let Terminator::Branch { cond: Operand::Imm(c), then, otherwise } = block.term {
+                block.term = Terminator::Jump(if c != 0 { then } else { otherwise });
+                changed = true;
+            }
+        }
+        if !changed {
+            return;
+        }
+    }
+}
+
+fn replace(op: &mut Operand, values: &HashMap<VReg, Operand>) -> bool {
+    if let Operand::Reg(r) = op {
+        if let Some(v) = values.get(r) {
+            *op = v.clone();
+            return true;
+        }
+    }
+    return false;
+}
+
+// The copy an operation can be replaced with.
+fn simplify(inst: &Inst) -> Option<Inst> {
+    let (dest, op, left, right) = match inst {
+        Inst::Binary { dest, op, left, right } => (*dest, *op, left, right),
+        _ => return None,
+    };
+    let src = match (left, right) {
+        (Operand::Imm(l), Operand::Imm(r)) => Operand::Imm(evaluate(op, *l, *r)),
+        (x, Operand::Imm(0)) if matches!(op, BinOp::Add | BinOp::Sub | BinOp::Xor) => x.clone(),
+        (Operand::Imm(0), x) if matches!(op, BinOp::Add | BinOp::Xor) => x.clone(),
+        (x, Operand::Imm(1)) | (Operand::Imm(1), x) if op == BinOp::Mul => x.clone(),
+        (_, Operand::Imm(0)) | (Operand::Imm(0), _) if matches!(op, BinOp::Mul | BinOp::And) => Operand::Imm(0),
+        (x, y) if x == y && matches!(op, BinOp::Sub | BinOp::Xor) => Operand::Imm(0),
+        _ => return None,
+    };
+    return Some(Inst::Copy { dest, src });
+}
+
+fn evaluate(op: BinOp, l: i64, r: i64) -> i64 {
+    match op {
+        BinOp::Add => l.wrapping_add(r),
+        BinOp::Sub => l.wrapping_sub(r),
+        BinOp::Mul => l.wrapping_mul(r),
+        BinOp::And => l & r,
+        BinOp::Xor => l ^ r,
+        BinOp::Lt => (l < r) as i64,
+        BinOp::Gt => (l > r) as i64,
+        BinOp::Le => (l <= r) as i64,
+        BinOp::Ge => (l >= r) as i64,
+        BinOp::Eq => (l == r) as i64,
+        BinOp::Ne => (l != r) as i64,
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{BlockId, Callee, Function, Inline, Inst, Module, Operand, Terminator, VReg};
+
+// Functions with at most this many instructions are always worth inlining.
+const SMALL_FUNCTION: usize = 16;
+// A function called from one place is inlined up to this size, the call and
+// the function itself go away.
+const CALLED_ONCE: usize = 64;
+// Callers stop growing through the heuristic at this size.
+const MAX_CALLER: usize = 1024;
+
+// Replaces direct calls with the body of the called function. Functions are
+// handled callees first, so their own calls are inlined before they are
+// copied. Recursive functions and closures are never inlined, @noinline
+// functions neither, and @inline functions always are. The functions must
+// not be in SSA form.
+pub fn inline_functions(module: &mut Module) {
+    let by_name: HashMap<String, usize> = module.functions.iter().enumerate().map(|(i, f)| (f.name.clone(), i)).collect();
+    let callees: Vec<Vec<usize>> = module.functions
+        .iter()
+        .map(|f| direct_calls(f).filter_map(|n| by_name.get(n).copied()).collect())
+        .collect();
+    let mut calls: HashMap<String, usize> = HashMap::new();
+    for function in &module.functions {
+        for name in direct_calls(function) {
+            *calls.entry(name.clone()).or_insert(0) += 1;
+        }
+    }
+    let recursive: HashSet<usize> = (0..callees.len()).filter(|f| reaches(&callees, *f, *f)).collect();
+
+    let mut visited = vec![false; callees.len()];
+    let mut order = Vec::new();
+    for f in 0..callees.len() {
+        postorder_from(f, &callees, &mut visited, &mut order);
+    }
+    for f in order {
+        let mut caller = std::mem::replace(&mut module.functions[f], Function::new(String::new()));
+        // The blocks that can still have calls to inline, the inlined blocks
+        // already had theirs.
+        let mut work: Vec<BlockId> = (0..caller.blocks.len()).rev().collect();
+        while let Some(b) = work.pop() {
+            let site = caller.blocks[b].insts.iter().position(|inst| match inst {
+                Inst::Call { callee: Callee::Direct(name), .. } => match by_name.get(name) {
+                    Some(c) => should_inline(&module.functions[*c], calls[name], recursive.contains(c), size(&caller)),
+                    None => false,
+                },
+                _ => false,
+            });
+            if let Some(i) = site {
+                let name = match &caller.blocks[b].insts[i] {
+                    Inst::Call { callee: Callee::Direct(name), .. } => name.clone(),
+                    _ => unreachable!(),
+                };
+                let rest = inline_call(&mut caller, b, i, &module.functions[by_name[&name]]);
+                work.push(rest);
+            }
+        }
+        module.functions[f] = caller;
+    }
+}
+
+fn should_inline(callee: &Function, calls: usize, recursive: bool, caller_size: usize) -> bool {
+    if recursive || callee.env.is_some() {
+        return false;
+    }
+    match callee.inline {
+        Inline::Always => return true,
+        Inline::Never => return false,
+        Inline::Auto => {}
+    }
+    let callee_size = size(callee);
+    if caller_size + callee_size > MAX_CALLER {
+        return false;
+    }
+    // Exported functions stay around for other modules, so copying them only pays off when they are small.
+    return callee_size <= SMALL_FUNCTION || (calls == 1 && !callee.exported && callee_size <= CALLED_ONCE);
+}
+
+fn size(function: &Function) -> usize {
+    return function.blocks.iter().map(|b| b.insts.len() + 1).sum();
+}
+
+fn direct_calls(function: &Function) -> impl Iterator<Item = &String> {
+    return function.blocks.iter().flat_map(|b| b.insts.iter()).filter_map(|inst| match inst {
+        Inst::Call { callee: Callee::Direct(name), .. } => Some(name),
+        _ => None,
+    });
+}
+
+fn reaches(callees: &[Vec<usize>], from: usize, to: usize) -> bool {
+    let mut seen = vec![false; callees.len()];
+    let mut work = callees[from].clone();
+    while let Some(f) = work.pop() {
+        if f == to {
+            return true;
+        }
+        if !seen[f] {
+            seen[f] = true;
+            work.extend(callees[f].iter().copied());
+        }
+    }
+    return false;
+}
+
+fn postorder_from(f: usize, callees: &[Vec<usize>], visited: &mut [bool], out: &mut Vec<usize>) {
+    if visited[f] {
+        return;
+    }
+    visited[f] = true;
+    for c in &callees[f] {
+        postorder_from(*c, callees, visited, out);
+    }
+    out.push(f);
+}
+
+// Replaces the call at `insts[i]` of block `b` with a copy of the callee. The
+// block ends in a jump to the callee's entry, its parameters are copies of
+// the arguments and its returns copy their values into the call's results
+// and jump to a new block with the instructions after the call. Every
+// register and block of the callee gets a fresh one in the caller. Returns
+// the new block.
+fn inline_call(caller: &mut Function, b: BlockId, i: usize, callee: &Function) -> BlockId {
+    let (dests, args) = match caller.blocks[b].insts.remove(i) {
+        Inst::Call { dests, args, .. } => (dests, args),
+        _ => unreachable!(),
+    };
+    let regs: Vec<VReg> = callee.regs.iter().map(|ty| caller.new_reg(*ty)).collect();
+    let blocks: Vec<BlockId> = callee.blocks.iter().map(|_| caller.new_block()).collect();
+    let rest = caller.new_block();
+    let tail = caller.blocks[b].insts.split_off(i);
+    caller.blocks[rest].insts = tail;
+    caller.blocks[rest].term = std::mem::replace(&mut caller.blocks[b].term, Terminator::Jump(blocks[0]));
+    for (param, arg) in callee.params.iter().zip(args) {
+        caller.blocks[b].insts.push(Inst::Copy { dest: regs[*param], src: arg });
+    }
+
+    let rename = |op: &mut Operand| {
+        if let Operand::Reg(r) = op {
+            *r = regs[*r];
+        }
+    };
+    for (id, block) in callee.blocks.iter().enumerate() {
+        let mut insts = block.insts.clone();
+        for inst in &mut insts {
+            inst.operands_mut().into_iter().for_each(rename);
+            match inst {
+                Inst::Copy { dest, .. } | Inst::Binary { dest, .. } | Inst::Load { dest, .. } => *dest = regs[*dest],
+                Inst::Call { dests, .. } => dests.iter_mut().for_each(|d| *d = regs[*d]),
+                Inst::Phi { dest, args } => {
+                    *dest = regs[*dest];
+                    args.iter_mut().for_each(|(p, _)| *p = blocks[*p]);
+                }
+                Inst::Store { .. } => {}
+            }
+        }
+        let mut term = block.term.clone();
+        term.operands_mut().into_iter().for_each(rename);
+        term = match term {
+            Terminator::Jump(s) => Terminator::Jump(blocks[s]),
+            Terminator::Branch { cond, then, otherwise } => Terminator::Branch { cond, then: blocks[then], otherwise: blocks[otherwise] },
+            Terminator::Return(values) => {
+                for (dest, value) in dests.iter().zip(values) {
+                    insts.push(Inst::Copy { dest: *dest, src: value });
+                }
+                Terminator::Jump(rest)
+            }
+            Terminator::Unreachable => Terminator::Unreachable,
+        };
+        caller.blocks[blocks[id]].insts = insts;
+        caller.blocks[blocks[id]].term = term;
+    }
+    return rest;
+}
@@ -0,0 +1,28 @@
+// Passes over the IR. Functions are taken into SSA form, optimized and taken
+// out of it again before the backend sees them.
+use crate::ir::Module;
+
+mod cfg;
+mod dce;
+mod dominators;
+mod fold;
+mod inline;
+mod ssa;
+
+use dce::{eliminate_dead_code, merge_blocks, remove_unreachable_blocks, remove_unused_functions};
+use fold::fold_constants;
+use inline::inline_functions;
+use ssa::{into_ssa, out_of_ssa};
+
+pub fn optimize(module: &mut Module) {
+    inline_functions(module);
+    for function in &mut module.functions {
+        into_ssa(function);
+        fold_constants(function);
+        out_of_ssa(function);
+        merge_blocks(function);
+        remove_unreachable_blocks(function);
+        eliminate_dead_code(function);
+    }
+    remove_unused_functions(module);
+}
@@ -0,0 +1,212 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{BlockId, Function, Inst, Operand, Terminator, VReg};
+use super::cfg::Cfg;
+use super::dominators::Dominators;
+
+// Rewrites a function into SSA form: every register assigned more than once is
+// split into one register per assignment, and phis are put where the values
+// of different paths meet. Only registers that live across blocks get phis.
+pub fn into_ssa(function: &mut Function) {
+    let cfg = Cfg::new(function);
+    let dominators = Dominators::new(&cfg);
+
+    // Where each register is assigned and which ones are read in a block
+    // before the block assigns them.
+    let mut def_blocks: HashMap<VReg, Vec<BlockId>> = HashMap::new();
+    let mut def_count: HashMap<VReg, usize> = HashMap::new();
+    let mut live_across: HashSet<VReg> = HashSet::new();
+    let entry_defs = function.params.iter().chain(function.env.iter());
+    for reg in entry_defs {
+        def_blocks.entry(*reg).or_default().push(0);
+        *def_count.entry(*reg).or_default() += 1;
+    }
+    for b in &cfg.order {
+        let block = &function.blocks[*b];
+        let mut killed = HashSet::new();
+        for inst in &block.insts {
+            for op in inst.operands() {
+                if let Operand::Reg(r) = op {
+                    if !killed.contains(r) {
+                        live_across.insert(*r);
+                    }
+                }
+            }
+            for d in inst.defs() {
+                killed.insert(d);
+                def_blocks.entry(d).or_default().push(*b);
+                *def_count.entry(d).or_default() += 1;
+            }
+        }
+        for op in block.term.operands() {
+            if let Operand::Reg(r) = op {
+                if !killed.contains(r) {
+                    live_across.insert(*r);
+                }
+            }
+        }
+    }
+
+    // Phis go into the iterated dominance frontier of the assignments.
+    let mut phis: Vec<Vec<VReg>> = vec![Vec::new(); function.blocks.len()];
+    let mut renamed: HashSet<VReg> = def_count.iter().filter(|(_, c)| **c > 1).map(|(r, _)| *r).collect();
+    let mut candidates: Vec<VReg> = renamed.iter().copied().filter(|r| live_across.contains(r)).collect();
+    candidates.sort();
+    for reg in candidates {
+        let mut work = def_blocks[&reg].clone();
+        let mut has_def: HashSet<BlockId> = work.iter().copied().collect();
+        while let Some(b) = work.pop() {
+            for f in &dominators.frontiers[b] {
+                if phis[*f].contains(&reg) {
+                    continue;
+                }
+                phis[*f].push(reg);
+                if has_def.insert(*f) {
+                    work.push(*f);
+                }
+            }
+        }
+    }
+    for (b, regs) in phis.iter().enumerate() {
+        let phi_insts = regs.iter().map(|r| Inst::Phi { dest: *r, args: Vec::new() });
+        function.blocks[b].insts.splice(0..0, phi_insts);
+        renamed.extend(regs.iter().copied());
+    }
+
+    let mut renamer = Renamer {
+        function,
+        cfg: &cfg,
+        dominators: &dominators,
+        phis: &phis,
+        renamed: &renamed,
+        stacks: HashMap::new(),
+    };
+    for reg in renamer.function.params.clone().into_iter().chain(renamer.function.env) {
+        renamer.stacks.entry(reg).or_default().push(reg);
+    }
+    renamer.rename(0);
+}
+
+struct Renamer<'a> {
+    function: &'a mut Function,
+    cfg: &'a Cfg,
+    dominators: &'a Dominators,
+    // The original register of every phi of a block, in order.
+    phis: &'a Vec<Vec<VReg>>,
+    renamed: &'a HashSet<VReg>,
+    // The current name of every renamed register.
+    stacks: HashMap<VReg, Vec<VReg>>,
+}
+
+impl Renamer<'_> {
+    // A register read before any assignment on a path is 0, like a variable without a value.
+    fn current(&self, reg: VReg) -> Operand {
+        match self.stacks.get(&reg).and_then(|s| s.last()) {
+            Some(r) => Operand::Reg(*r),
+            None => Operand::Imm(0),
+        }
+    }
+
+    fn rename(&mut self, b: BlockId) {
+        let mut pushed = Vec::new();
+        let mut insts = std::mem::take(&mut self.function.blocks[b].insts);
+        for inst in &mut insts {
+            if !matches!(inst, Inst::Phi { .. }) {
+                for op in inst.operands_mut() {
+                    if let Operand::Reg(r) = op {
+                        if self.renamed.contains(r) {
+                            *op = self.current(*r);
+                        }
+                    }
+                }
+            }
+            for dest in defs_mut(inst) {
+                if self.renamed.contains(dest) {
+                    let new = self.function.new_reg(self.function.regs[*dest]);
+                    self.stacks.entry(*dest).or_default().push(new);
+                    pushed.push(*dest);
+                    *dest = new;
+                }
+            }
+        }
+        self.function.blocks[b].insts = insts;
+        let mut term = std::mem::replace(&mut self.function.blocks[b].term, Terminator::Unreachable);
+        for op in term.operands_mut() {
+            if let Operand::Reg(r) = op {
+                if self.renamed.contains(r) {
+                    *op = self.current(*r);
+                }
+            }
+        }
+        self.function.blocks[b].term = term;
+
+        for s in &self.cfg.succs[b] {
+            for (i, reg) in self.phis[*s].iter().enumerate() {
+                let value = self.current(*reg);
+                if let Inst::Phi { args, .. } = &mut self.function.blocks[*s].insts[i] {
+                    if !args.iter().any(|(p, _)| *p == b) {
+                        args.push((b, value));
+                    }
+                }
+            }
+        }
+        for child in self.dominators.children[b].clone() {
+            self.rename(child);
+        }
+        for reg in pushed {
+            self.stacks.get_mut(&reg).unwrap().pop();
+        }
+    }
+}
+
+fn defs_mut(inst: &mut Inst) -> Vec<&mut VReg> {
+    match inst {
+        Inst::Copy { dest, .. } | Inst::Binary { dest, .. } | Inst::Load { dest, .. } |
+        Inst::Phi { dest, .. } => vec![dest],
+        Inst::Call { dests, .. } => dests.iter_mut().collect(),
+        Inst::Store { .. } => Vec::new(),
+    }
+}
+
+// Replaces the phis by copies. Every phi gets a register of its own that is
+// set on each incoming edge and copied at the start of the block, so phis
+// reading each other stay correct. Edges from blocks with more than one
+// successor are split, the copies would run on the other edge as well.
+pub fn out_of_ssa(function: &mut Function) {
+    let mut split: HashMap<(BlockId, BlockId), BlockId> = HashMap::new();
+    for b in 0..function.blocks.len() {
+        let mut i = 0;
+        while let Some(Inst::Phi { dest, args }) = function.blocks[b].insts.get(i).cloned() {
+            let tmp = function.new_reg(function.regs[dest]);
+            function.blocks[b].insts[i] = Inst::Copy { dest, src: Operand::Reg(tmp) };
+            for (pred, value) in args {
+                let edge = edge_block(function, &mut split, pred, b);
+                function.blocks[edge].insts.push(Inst::Copy { dest: tmp, src: value });
+            }
+            i += 1;
+        }
+    }
+}
+
+fn edge_block(function: &mut Function, split: &mut HashMap<(BlockId, BlockId), BlockId>, pred: BlockId, succ: BlockId) -> BlockId {
+    let mut succs = function.blocks[pred].term.successors();
+    succs.dedup();
+    if succs.len() < 2 {
+        return pred;
+    }
+    if let Some(e) = split.get(&(pred, succ)) {
+        return *e;
+    }
+    let e = function.new_block();
+    function.blocks[e].term = Terminator::Jump(succ);
+    if let Terminator::Branch { then, otherwise, .. } = &mut function.blocks[pred].term {
+        if *then == succ {
+            *then = e;
+        }
+        if *otherwise == succ {
+            *otherwise = e;
+        }
+    }
+    split.insert((pred, succ), e);
+    return e;
+}
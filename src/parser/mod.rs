@@ -23,6 +23,8 @@ pub struct Parser {
     cur: Token,
     next: Token,
     program: Program,
+    // Where every expression of the program starts.
+    spans: Vec<Span>,
 }
 
 
@@ -32,11 +34,23 @@ impl Parser {
             cur: t.next_token(),
             next: t.next_token(),
             program: Vec::new(),
+            spans: Vec::new(),
             t,
         };
     }
 
 
+    // Parses into an existing program, so the expressions of several files share one arena.
+    pub fn with_program(mut t: Tokenizer, program: Program, spans: Vec<Span>) -> Self {
+        return Parser {
+            cur: t.next_token(),
+            next: t.next_token(),
+            program,
+            spans,
+            t,
+        };
+    }
+
     fn shift(&mut self) {
         self.cur = self.next.clone();
         self.next = self.t.next_token();
@@ -51,11 +65,12 @@ impl Parser {
             }
 
             TokenType::Minus => {
+                let span = self.cur.span;
                 self.shift();
                 let operand = self.parse(Prio::Prefix);
-                self.new_expression(Box::new(
+                self.new_expression_at(Box::new(
                     Expression::PrefixExpression { op: TokenType::Minus, right: operand }
-                ))
+                ), span)
             }
 
             _ => {
@@ -67,6 +82,9 @@ impl Parser {
     pub fn get_program(&self) -> Program {
         return self.program.clone();
     }
+    pub fn get_spans(&self) -> Vec<Span> {
+        return self.spans.clone();
+    }
     fn parse_block(&mut self) -> Vec<Box<Statement>> {
         self.shift();
         self.shift();
@@ -92,35 +110,52 @@ impl Parser {
                 );
                 self.new_expression(Box::new(exp))
             }
+            TokenType::Char => {
+                let exp = Expression::Char(
+                    self.cur.value.as_bytes()[0]
+                );
+                self.new_expression(Box::new(exp))
+            }
             _ => {
                 panic!("I Don't Know What Happend Here");
             }
         }
     }
     fn parse_param_list(&mut self) -> Vec<Parameter> {
+        return self.parse_params(false).0;
+    }
+
+    // Extern functions can end their parameters with ... to take any number of arguments.
+    fn parse_params(&mut self, allow_variadic: bool) -> (Vec<Parameter>, bool) {
         if self.cur.token_type != TokenType::LParent {
             panic!("No Left Parenteses Found {}", self);
         }
         self.shift();
         let mut list: Vec<Parameter> = Vec::new();
         while self.cur.token_type != TokenType::RParent {
+            if self.cur.token_type == TokenType::Ellipsis {
+                if !allow_variadic || self.next.token_type != TokenType::RParent {
+                    panic!("... has to be the last parameter of an extern function {}", self);
+                }
+                self.shift();
+                return (list, true);
+            }
             if self.cur.token_type != TokenType::Identifier {
                 panic!("NOT AN IDENTIFIER IN PARAMETER LIST");
             }
             let name = self.cur.value.clone();
+            let span = self.cur.span;
             self.shift();
             if self.cur.token_type != TokenType::Colon {
                 panic!("NO TYPE ANOTATION");
             }
             self.shift();
-            if self.cur.token_type != TokenType::Identifier {
-                panic!("TYPE MISSING IN PARAMETER LIST");
-            }
-            let param_type = self.cur.value.clone();
+            let param_type = self.parse_type();
 
             list.push(Parameter{
                 name,
-                param_type
+                param_type,
+                span
             });
 
             self.shift();
@@ -128,7 +163,7 @@ impl Parser {
                 self.shift();
             }
         }
-        return list;
+        return (list, false);
     }
 
     fn parse_list(&mut self) -> Vec<ExpRef> {
@@ -147,6 +182,220 @@ impl Parser {
         }
         return list;
     }
+    // Leaves the last token of the type in cur.
+    fn parse_type(&mut self) -> Type {
+        match self.cur.token_type {
+            TokenType::Identifier => {
+                let name = self.parse_path().join(".");
+                if self.next.token_type == TokenType::LT {
+                    self.shift();
+                    return Type::Generic { name, args: self.parse_type_args() };
+                }
+                return match name.as_str() {
+                    "int" => Type::Int,
+                    "char" => Type::Char,
+                    "string" => Type::String,
+                    _ => Type::Named(name),
+                };
+            }
+            TokenType::Astrik => {
+                self.shift();
+                return Type::Pointer(Box::new(self.parse_type()));
+            }
+            // [int; 4]
+            TokenType::LBrack => {
+                self.shift();
+                let element = self.parse_type();
+                self.shift();
+                if self.cur.token_type != TokenType::Semicolon || self.next.token_type != TokenType::Integer {
+                    panic!("Array types look like [type; length] {}", self);
+                }
+                self.shift();
+                let len = self.cur.value.parse().unwrap();
+                self.shift();
+                if self.cur.token_type != TokenType::RBrack {
+                    panic!("Missing ] after array type {}", self);
+                }
+                return Type::Array(Box::new(element), len);
+            }
+            TokenType::LParent => {
+                return Type::Tuple(self.parse_type_list());
+            }
+            TokenType::Func => {
+                self.shift();
+                let params = self.parse_type_list();
+                if self.next.token_type != TokenType::Colon {
+                    return Type::function(params, None);
+                }
+                self.shift();
+                self.shift();
+                return Type::function(params, Some(self.parse_type()));
+            }
+            _ => {
+                panic!("Missing Type {}", self);
+            }
+        }
+    }
+
+    // <T, U> after the name of a generic function or struct, leaves the token after > in cur.
+    fn parse_type_params(&mut self) -> Vec<String> {
+        let mut params = Vec::new();
+        if self.cur.token_type != TokenType::LT {
+            return params;
+        }
+        self.shift();
+        while self.cur.token_type != TokenType::GT {
+            if self.cur.token_type != TokenType::Identifier {
+                panic!("NOT AN IDENTIFIER IN TYPE PARAMETERS {}", self);
+            }
+            params.push(self.cur.value.clone());
+            self.shift();
+            if self.cur.token_type == TokenType::Comma {
+                self.shift();
+            }
+        }
+        self.shift();
+        return params;
+    }
+
+    // <int, (int, int)> with the < in cur, leaves the > in cur.
+    fn parse_type_args(&mut self) -> Vec<Type> {
+        self.shift();
+        let mut args = Vec::new();
+        while self.cur.token_type != TokenType::GT {
+            args.push(self.parse_type());
+            self.shift();
+            if self.cur.token_type == TokenType::Comma {
+                self.shift();
+            }
+        }
+        return args;
+    }
+
+    // Checks without consuming anything if the < in next starts type
+    // arguments like in max<int>(a, b) or var f: func(int, int): int = max<int>;
+    fn is_type_args(&self) -> bool {
+        let mut t = self.t.clone();
+        let mut depth = 1;
+        loop {
+            match t.next_token().token_type {
+                TokenType::LT => depth += 1,
+                TokenType::GT => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return matches!(t.next_token().token_type,
+                            TokenType::LParent | TokenType::Semicolon | TokenType::Comma | TokenType::RParent);
+                    }
+                }
+                TokenType::Identifier | TokenType::Comma | TokenType::Colon | TokenType::Dot |
+                TokenType::LParent | TokenType::RParent | TokenType::Func | TokenType::Astrik |
+                TokenType::LBrack | TokenType::RBrack | TokenType::Semicolon | TokenType::Integer => {}
+                _ => return false,
+            }
+        }
+    }
+
+    fn parse_type_list(&mut self) -> Vec<Type> {
+        if self.cur.token_type != TokenType::LParent {
+            panic!("No Left Parenteses Found {}", self);
+        }
+        self.shift();
+        let mut elements = Vec::new();
+        while self.cur.token_type != TokenType::RParent {
+            elements.push(self.parse_type());
+            self.shift();
+            if self.cur.token_type == TokenType::Comma {
+                self.shift();
+            }
+        }
+        return elements;
+    }
+
+    // var (a, b) = value;
+    fn parse_destructure(&mut self, span: Span) -> Statement {
+        self.shift();
+        let mut names = Vec::new();
+        while self.cur.token_type != TokenType::RParent {
+            if self.cur.token_type != TokenType::Identifier {
+                panic!("NOT AN IDENTIFIER IN DESTRUCTURING {}", self);
+            }
+            names.push(self.cur.value.clone());
+            self.shift();
+            if self.cur.token_type == TokenType::Comma {
+                self.shift();
+            }
+        }
+        self.shift();
+        if self.cur.token_type != TokenType::Assign {
+            panic!("Destructuring needs a value {}", self);
+        }
+        self.shift();
+        let value = self.parse(Prio::None);
+        self.shift();
+        self.shift();
+        return Statement::DestructureStatement { names, span, value };
+    }
+
+    // a.b.c with the first identifier in cur, leaves the last one in cur.
+    fn parse_path(&mut self) -> Vec<String> {
+        let mut path = vec![self.cur.value.clone()];
+        while self.next.token_type == TokenType::Dot {
+            self.shift();
+            self.shift();
+            if self.cur.token_type != TokenType::Identifier {
+                panic!("NOT AN IDENTIFIER IN PATH {}", self);
+            }
+            path.push(self.cur.value.clone());
+        }
+        return path;
+    }
+
+    fn parse_pattern(&mut self) -> Pattern {
+        if self.cur.token_type != TokenType::Identifier {
+            panic!("NOT A VALID PATTERN {}", self);
+        }
+        if self.cur.value == "_" {
+            self.shift();
+            return Pattern::Wildcard;
+        }
+        let mut path = self.parse_path();
+        if path.len() < 2 {
+            panic!("Pattern has to look like Enum.Variant {}", self);
+        }
+        self.shift();
+        let variant = path.pop().unwrap();
+        return Pattern::Variant { enum_name: path.join("."), variant };
+    }
+    // @name or @name(arg, ...), one or more in a row. Stops on the token after the last one.
+    fn parse_attributes(&mut self) -> Vec<Attribute> {
+        let mut attributes = Vec::new();
+        while self.cur.token_type == TokenType::At {
+            let span = self.cur.span;
+            self.shift();
+            if self.cur.token_type != TokenType::Identifier {
+                panic!("Expected the name of an attribute {}", self);
+            }
+            let name = self.cur.value.clone();
+            let mut args = Vec::new();
+            if self.next.token_type == TokenType::LParent {
+                self.shift();
+                self.shift();
+                while self.cur.token_type != TokenType::RParent {
+                    if self.cur.token_type != TokenType::Identifier {
+                        panic!("Arguments of @{} have to be names {}", name, self);
+                    }
+                    args.push(self.cur.value.clone());
+                    self.shift();
+                    if self.cur.token_type == TokenType::Comma {
+                        self.shift();
+                    }
+                }
+            }
+            self.shift();
+            attributes.push(Attribute { name, args, span });
+        }
+        return attributes;
+    }
     pub fn parse_program(&mut self) -> Vec<Statement> {
         let mut v = Vec::new();
         while self.next.token_type != TokenType::EOF {
@@ -155,24 +404,27 @@ impl Parser {
         return v;
     }
     pub fn parse_stmt(&mut self) -> Statement {
+        let span = self.cur.span;
         let stmt = match self.cur.token_type {
             TokenType::Var => {
                 self.shift();
-                let name = self.cur.value.clone();
-                if self.next.token_type != TokenType::Colon {
-                    panic!("Missing Type");
+                if self.cur.token_type == TokenType::LParent {
+                    return self.parse_destructure(span);
                 }
-                self.shift();
-                if self.next.token_type != TokenType::Identifier {
-                    panic!("Missing Type");
+                let name = self.cur.value.clone();
+                // Without a type it is inferred from the value by the type checker.
+                let mut var_type = None;
+                if self.next.token_type == TokenType::Colon {
+                    self.shift();
+                    self.shift();
+                    var_type = Some(self.parse_type());
                 }
-                self.shift();
-                let var_type = self.cur.value.clone();
                 if self.next.token_type == TokenType::Semicolon {
                     self.shift();
                     self.shift();
                     return Statement::VarStatement { 
                         name,
+                        span,
                         value: None,
                         var_type
                     }
@@ -184,6 +436,7 @@ impl Parser {
                 self.shift();
                 return Statement::VarStatement { 
                     name,
+                    span,
                     value: Some(value),
                     var_type
                 }
@@ -196,6 +449,7 @@ impl Parser {
                 println!("DONE IFF");
                 println!("ELSE, {}", self);
                 if self.next.token_type != TokenType::Else {
+                    self.shift();
                     return Statement::IfElseStatement { 
                         condition: cond, 
                         if_body: if_block, 
@@ -215,28 +469,188 @@ impl Parser {
             }
             TokenType::Return => {
                 self.shift();
+                if self.cur.token_type == TokenType::Semicolon {
+                    self.shift();
+                    return Statement::ReturnStatement { span, value: None };
+                }
                 let s = Statement::ReturnStatement{
-                    value: self.parse(Prio::None)
+                    span,
+                    value: Some(self.parse(Prio::None))
                 };
                 self.shift();
                 self.shift();
                 s
             }
+            TokenType::Enum => {
+                self.shift();
+                if self.cur.token_type != TokenType::Identifier {
+                    panic!("ENUM NAME MISSING");
+                }
+                let name = self.cur.value.clone();
+                self.shift();
+                if self.cur.token_type != TokenType::LBrace {
+                    panic!("No Left Brace Found {}", self);
+                }
+                self.shift();
+                let mut variants = Vec::new();
+                while self.cur.token_type != TokenType::RBrace {
+                    if self.cur.token_type != TokenType::Identifier {
+                        panic!("NOT AN IDENTIFIER IN ENUM {}", name);
+                    }
+                    variants.push(self.cur.value.clone());
+                    self.shift();
+                    if self.cur.token_type == TokenType::Comma {
+                        self.shift();
+                    }
+                }
+                self.shift();
+                Statement::EnumStatement { name, public: false, span, variants }
+            }
+            TokenType::Match => {
+                self.shift();
+                let value = self.parse(Prio::None);
+                self.shift();
+                if self.cur.token_type != TokenType::LBrace {
+                    panic!("No Left Brace Found {}", self);
+                }
+                self.shift();
+                let mut arms = Vec::new();
+                while self.cur.token_type != TokenType::RBrace {
+                    let pattern = self.parse_pattern();
+                    if self.cur.token_type != TokenType::Arrow {
+                        panic!("Missing => in match arm {}", self);
+                    }
+                    let body = self.parse_block();
+                    self.shift();
+                    if self.cur.token_type == TokenType::Comma {
+                        self.shift();
+                    }
+                    arms.push(MatchArm { pattern, body });
+                }
+                self.shift();
+                Statement::MatchStatement { value, arms }
+            }
+            TokenType::Struct => {
+                self.shift();
+                if self.cur.token_type != TokenType::Identifier {
+                    panic!("STRUCT NAME MISSING");
+                }
+                let name = self.cur.value.clone();
+                self.shift();
+                let type_params = self.parse_type_params();
+                if self.cur.token_type != TokenType::LBrace {
+                    panic!("No Left Brace Found {}", self);
+                }
+                self.shift();
+                let mut fields = Vec::new();
+                while self.cur.token_type != TokenType::RBrace {
+                    if self.cur.token_type != TokenType::Identifier {
+                        panic!("NOT AN IDENTIFIER IN STRUCT {}", name);
+                    }
+                    let field_name = self.cur.value.clone();
+                    let field_span = self.cur.span;
+                    self.shift();
+                    if self.cur.token_type != TokenType::Colon {
+                        panic!("NO TYPE ANOTATION");
+                    }
+                    self.shift();
+                    fields.push(Parameter {
+                        name: field_name,
+                        param_type: self.parse_type(),
+                        span: field_span,
+                    });
+                    self.shift();
+                    if self.cur.token_type == TokenType::Comma {
+                        self.shift();
+                    }
+                }
+                self.shift();
+                Statement::StructStatement { name, public: false, span, type_params, fields }
+            }
+            TokenType::Pub => {
+                self.shift();
+                let mut stmt = self.parse_stmt();
+                match &mut stmt {
+                    Statement::FuncStatement { public, .. } |
+                    Statement::EnumStatement { public, .. } |
+                    Statement::StructStatement { public, .. } => {
+                        *public = true;
+                    }
+                    _ => {
+                        panic!("Only functions, enums and structs can be pub");
+                    }
+                }
+                stmt
+            }
+            TokenType::At => {
+                let attributes = self.parse_attributes();
+                let mut stmt = self.parse_stmt();
+                match &mut stmt {
+                    Statement::FuncStatement { attributes: a, .. } => {
+                        a.extend(attributes);
+                    }
+                    _ => {
+                        panic!("Attributes can only be put on functions");
+                    }
+                }
+                stmt
+            }
+            TokenType::Extern => {
+                self.shift();
+                if self.cur.token_type != TokenType::Func {
+                    panic!("Only functions can be extern {}", self);
+                }
+                self.shift();
+                let name = self.cur.value.clone();
+                self.shift();
+                let (call_inputs, variadic) = self.parse_params(true);
+                let mut return_type: Option<Type> = None;
+                if self.next.token_type == TokenType::Colon {
+                    self.shift();
+                    self.shift();
+                    return_type = Some(self.parse_type());
+                }
+                self.shift();
+                if self.cur.token_type != TokenType::Semicolon {
+                    panic!("Missing Semicolon after extern function {}", self);
+                }
+                self.shift();
+                Statement::ExternStatement { name, span, call_inputs, return_type, variadic }
+            }
+            TokenType::Import => {
+                self.shift();
+                let path = match self.cur.token_type {
+                    TokenType::String => self.cur.value.split('/').map(|p| p.to_string()).collect(),
+                    TokenType::Identifier => self.parse_path(),
+                    _ => panic!("Import has to look like import \"util\"; or import util.math; {}", self),
+                };
+                self.shift();
+                if self.cur.token_type != TokenType::Semicolon {
+                    panic!("Missing Semicolon after import {}", self);
+                }
+                self.shift();
+                Statement::ImportStatement { path }
+            }
             TokenType::Func => {
                 self.shift();
                 let name = self.cur.value.clone();
                 self.shift();
+                let type_params = self.parse_type_params();
                 let list = self.parse_param_list();
-                let mut func_type: Option<String> = None;
+                let mut func_type: Option<Type> = None;
                 if self.next.token_type == TokenType::Colon {
                     self.shift();
                     self.shift();
-                    func_type = Some(self.cur.value.clone());
+                    func_type = Some(self.parse_type());
                 }
                 let body = self.parse_block();
                 self.shift();
                 Statement::FuncStatement { 
                     name: name.clone(),
+                    public: false,
+                    span,
+                    attributes: Vec::new(),
+                    type_params,
                     call_inputs: list,
                     return_type: func_type,
                     body 
@@ -260,7 +674,7 @@ impl Parser {
             TokenType::Astrik | TokenType::Slash => {
                 return Prio::Mult
             }
-            TokenType::LParent => {
+            TokenType::LParent | TokenType::Dot | TokenType::LBrack => {
                 return Prio::Call
             }
             TokenType::Assign => {
@@ -275,7 +689,7 @@ impl Parser {
             TokenType::LT | TokenType::GT | TokenType::LTEQ | TokenType::GTEQ => {
                 return Prio::Compare
             }
-            TokenType::EQ => {
+            TokenType::EQ | TokenType::NotEQ => {
                 return Prio::Equal;
             }
 
@@ -287,16 +701,45 @@ impl Parser {
         }
     }
 
+    // Expressions built on top of `left` start where `left` starts.
     fn parse_infix(&mut self, left: ExpRef) -> ExpRef {
+        let span = self.spans[left];
         if self.cur.token_type == TokenType::LParent {
             let parameters = self.parse_list();
-            return self.new_expression(
+            return self.new_expression_at(
                 Box::new(Expression::FunctionCall {
                     left,
                     parameters 
-                })
+                }),
+                span
             )
         }
+        if self.cur.token_type == TokenType::Dot {
+            self.shift();
+            let field = self.cur.value.clone();
+            // module.func<T> refers to a generic function of an imported module
+            if let Expression::Identifier { value, .. } = &*self.program[left] {
+                if self.next.token_type == TokenType::LT && self.is_type_args() {
+                    let value = format!("{}.{}", value, field);
+                    self.shift();
+                    let type_args = self.parse_type_args();
+                    return self.new_expression_at(Box::new(Expression::GenericInstance {
+                        value,
+                        type_args
+                    }), span);
+                }
+            }
+            return self.new_expression_at(
+                Box::new(Expression::FieldAccess {
+                    left,
+                    field
+                }),
+                span
+            )
+        }
+        if self.cur.token_type == TokenType::LBrack {
+            return self.parse_index(left);
+        }
         let op = self.cur.token_type.clone();
         let p = Self::get_prio(&self.cur.token_type);
         self.shift();
@@ -307,7 +750,78 @@ impl Parser {
             right,
         };
 
-        return self.new_expression(Box::new(exp))
+        return self.new_expression_at(Box::new(exp), span)
+    }
+
+    // s[i], s[a:b], s[:b], s[a:] and s[:]
+    fn parse_index(&mut self, left: ExpRef) -> ExpRef {
+        self.shift();
+        let mut start = None;
+        if self.cur.token_type != TokenType::Colon {
+            let index = self.parse(Prio::None);
+            self.shift();
+            if self.cur.token_type == TokenType::RBrack {
+                return self.new_expression_at(Box::new(Expression::Index { left, index }), self.spans[left]);
+            }
+            if self.cur.token_type != TokenType::Colon {
+                panic!("NO RIGHT BRACKET FOUND {}", self);
+            }
+            start = Some(index);
+        }
+        self.shift();
+        let mut end = None;
+        if self.cur.token_type != TokenType::RBrack {
+            end = Some(self.parse(Prio::None));
+            self.shift();
+            if self.cur.token_type != TokenType::RBrack {
+                panic!("NO RIGHT BRACKET FOUND {}", self);
+            }
+        }
+        return self.new_expression_at(Box::new(Expression::Slice { left, start, end }), self.spans[left]);
+    }
+
+    // func(x: int): int { ... } used as a value
+    fn parse_lambda(&mut self) -> ExpRef {
+        let span = self.cur.span;
+        self.shift();
+        let call_inputs = self.parse_param_list();
+        let mut return_type: Option<Type> = None;
+        if self.next.token_type == TokenType::Colon {
+            self.shift();
+            self.shift();
+            return_type = Some(self.parse_type());
+        }
+        let body = self.parse_block();
+        return self.new_expression_at(Box::new(Expression::Lambda {
+            call_inputs,
+            return_type,
+            body
+        }), span);
+    }
+
+    // (a) or the tuple (a, b, ...)
+    fn parse_group(&mut self) -> ExpRef {
+        let span = self.cur.span;
+        self.shift();
+        let l = self.parse(Prio::None);
+        if self.next.token_type != TokenType::Comma {
+            if self.next.token_type != TokenType::RParent {
+                panic!("NO RIGHT PARENTH FOUND");
+            }
+            self.shift();
+            return l;
+        }
+        let mut elements = vec![l];
+        while self.next.token_type == TokenType::Comma {
+            self.shift();
+            self.shift();
+            elements.push(self.parse(Prio::None));
+        }
+        if self.next.token_type != TokenType::RParent {
+            panic!("NO RIGHT PARENTH FOUND");
+        }
+        self.shift();
+        return self.new_expression_at(Box::new(Expression::Tuple(elements)), span);
     }
 
     fn parse(&mut self, p: Prio) -> ExpRef  {
@@ -315,24 +829,31 @@ impl Parser {
             TokenType::Plus | TokenType::Minus=> {
                 self.parse_prefix()
             }
-            TokenType::Integer | TokenType::String => {
+            TokenType::Integer | TokenType::String | TokenType::Char => {
                 self.parse_literal()
             }
+            TokenType::Identifier if self.next.token_type == TokenType::LT && self.is_type_args() => {
+                let value = self.cur.value.clone();
+                let span = self.cur.span;
+                self.shift();
+                let type_args = self.parse_type_args();
+                self.new_expression_at(Box::new(Expression::GenericInstance {
+                    value,
+                    type_args
+                }), span)
+            }
             TokenType::Identifier => {
                 self.new_expression(Box::new(Expression::Identifier {
                     value: self.cur.value.clone(),
-                    ident_type: String::new(), 
+                    ident_type: Type::Any,
+                    decl: Declaration::Unresolved,
                 }))
             }
             TokenType::LParent => {
-                self.shift();
-                let l = self.parse(Prio::None);
-                if self.next.token_type != TokenType::RParent {
-                    panic!("NO RIGHT PARENTH FOUND");
-                }
-                self.shift();
-
-                l
+                self.parse_group()
+            }
+            TokenType::Func => {
+                self.parse_lambda()
             }
 
             _ => {
@@ -372,9 +893,53 @@ impl Parser {
             Expression::String(i) => {
                 return format!("{}", i);
             }
-            Expression::Identifier { value, ident_type } => {
+            Expression::Char(c) => {
+                return format!("{:?}", c as char);
+            }
+            Expression::Identifier { value, .. } => {
                 return format!("{}", value);
             }
+            Expression::FieldAccess { left, field } => {
+                return format!("{}.{}", self.exp_to_string(left), field);
+            }
+            Expression::GenericInstance { value, type_args } => {
+                return format!("{}", Type::Generic { name: value, args: type_args });
+            }
+            Expression::Lambda { call_inputs, return_type, body } => {
+                let s = call_inputs
+                    .iter()
+                    .map(|x| format!("{}: {}", x.name, x.param_type))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                let body = body
+                    .into_iter()
+                    .map(|x| self.stmt_to_string(*x, 0).trim().to_string())
+                    .collect::<Vec<String>>()
+                    .join("; ");
+                match return_type {
+                    Some(r) => return format!("func({}): {} {{ {} }}", s, r, body),
+                    None => return format!("func({}) {{ {} }}", s, body),
+                }
+            }
+            Expression::Tuple(elements) => {
+                return format!("({})",
+                    elements
+                        .iter()
+                        .map(|x| self.exp_to_string(*x))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+            Expression::Index { left, index } => {
+                return format!("{}[{}]", self.exp_to_string(left), self.exp_to_string(index));
+            }
+            Expression::Slice { left, start, end } => {
+                return format!("{}[{}:{}]",
+                    self.exp_to_string(left),
+                    start.map(|x| self.exp_to_string(x)).unwrap_or_default(),
+                    end.map(|x| self.exp_to_string(x)).unwrap_or_default(),
+                );
+            }
             _ => {
                 return format!("");
             }
@@ -410,7 +975,7 @@ impl Parser {
                 val += "}\n";
                 return val;
             }
-            Statement::FuncStatement { name, call_inputs, return_type, body } => {
+            Statement::FuncStatement { name, public, type_params, call_inputs, return_type, body, .. } => {
                 let s = call_inputs
                     .iter()
                     .map(|x| format!("{}: {}", x.name, x.param_type))
@@ -418,6 +983,14 @@ impl Parser {
                     .join(", ");
 
                 let mut val = indent.clone();
+                if public {
+                    val += "pub ";
+                }
+                let name = if type_params.is_empty() {
+                    name
+                } else {
+                    format!("{}<{}>", name, type_params.join(", "))
+                };
                 if return_type.is_some() {
                     val += &format!("func {}({}): {} {{\n", name, s, return_type.unwrap());
                 } else {
@@ -431,9 +1004,12 @@ impl Parser {
                 val += "}\n";
                 return val;
             }
-            Statement::VarStatement { name, value, var_type } => {
+            Statement::VarStatement { name, value, var_type, .. } => {
                 let mut val = indent.clone();
-                val += &format!("var {}: {}", name, var_type);
+                val += &format!("var {}", name);
+                if let Some(t) = var_type {
+                    val += &format!(": {}", t);
+                }
                 if value.is_none() {
                     return val + "\n";
                 }
@@ -441,11 +1017,66 @@ impl Parser {
                 val += &format!(" = {}\n", self.exp_to_string(v));
                 return val;
             }
-            Statement::ReturnStatement { value } => {
+            Statement::ReturnStatement { value, .. } => {
+                let value = value.map(|v| format!(" {}", self.exp_to_string(v))).unwrap_or_default();
                 if ident > 0 {
-                    return format!("\treturn {}\n", self.exp_to_string(value));
+                    return format!("\treturn{}\n", value);
                 } 
-                return format!("return {}\n", self.exp_to_string(value));
+                return format!("return{}\n", value);
+            }
+            Statement::DestructureStatement { names, value, .. } => {
+                return format!("{}var ({}) = {}\n", indent, names.join(", "), self.exp_to_string(value));
+            }
+            Statement::StructStatement { name, public, type_params, fields, .. } => {
+                let indent = if public { indent + "pub " } else { indent };
+                let fields = fields
+                    .iter()
+                    .map(|x| format!("{}: {}", x.name, x.param_type))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                if type_params.is_empty() {
+                    return format!("{}struct {} {{ {} }}\n", indent, name, fields);
+                }
+                return format!("{}struct {}<{}> {{ {} }}\n", indent, name, type_params.join(", "), fields);
+            }
+            Statement::EnumStatement { name, public, variants, .. } => {
+                let indent = if public { indent + "pub " } else { indent };
+                return format!("{}enum {} {{ {} }}\n", indent, name, variants.join(", "));
+            }
+            Statement::MatchStatement { value, arms } => {
+                let mut val = indent.clone();
+                val += &format!("match {} {{\n", self.exp_to_string(value));
+                for arm in arms {
+                    let pattern = match arm.pattern {
+                        Pattern::Variant { enum_name, variant } => format!("{}.{}", enum_name, variant),
+                        Pattern::Wildcard => String::from("_"),
+                    };
+                    val += &format!("{}\t{} => {{\n", indent, pattern);
+                    for i in arm.body {
+                        val += &indent;
+                        val += &self.stmt_to_string(*i, ident + 2);
+                    }
+                    val += &format!("{}\t}}\n", indent);
+                }
+                val += &indent;
+                val += "}\n";
+                return val;
+            }
+            Statement::ExternStatement { name, call_inputs, return_type, variadic, .. } => {
+                let mut params: Vec<String> = call_inputs
+                    .iter()
+                    .map(|x| format!("{}: {}", x.name, x.param_type))
+                    .collect();
+                if variadic {
+                    params.push(String::from("..."));
+                }
+                return match return_type {
+                    Some(r) => format!("{}extern func {}({}): {};\n", indent, name, params.join(", "), r),
+                    None => format!("{}extern func {}({});\n", indent, name, params.join(", ")),
+                };
+            }
+            Statement::ImportStatement { path } => {
+                return format!("{}import {};\n", indent, path.join("."));
             }
             Statement::ExpressionStatement(exp) => {
                 if ident > 0 {
@@ -459,7 +1090,12 @@ impl Parser {
 
 
     fn new_expression(&mut self, exp: Box<Expression>) -> ExpRef {
+        return self.new_expression_at(exp, self.cur.span);
+    }
+
+    fn new_expression_at(&mut self, exp: Box<Expression>, span: Span) -> ExpRef {
         self.program.push(exp);
+        self.spans.push(span);
         return self.program.len() - 1;
     }
 
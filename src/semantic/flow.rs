@@ -0,0 +1,169 @@
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+use crate::token::Span;
+
+use super::lint::{Levels, Lint, LintLevels};
+
+// Checks that functions with a return type return a value on every path,
+// that functions without one don't, and warns about statements that can't
+// be reached because every path before them returned.
+pub fn check_flow(stmts: &[&Statement], program: &Program, spans: &Vec<Span>, levels: &LintLevels) -> Vec<Diagnostic> {
+    let mut flow = Flow {
+        program,
+        spans,
+        levels: Levels::new(levels),
+        diagnostics: Vec::new(),
+    };
+    for stmt in stmts {
+        flow.stmt(stmt, None);
+    }
+    return flow.diagnostics;
+}
+
+struct Function<'f> {
+    name: &'f str,
+    return_type: &'f Option<Type>,
+}
+
+struct Flow<'a> {
+    program: &'a Program,
+    spans: &'a Vec<Span>,
+    levels: Levels<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Flow<'a> {
+    fn function(&mut self, name: &str, attributes: &[Attribute], return_type: &Option<Type>, body: &Vec<Box<Statement>>, span: Span) {
+        self.levels.enter(attributes);
+        let function = Function { name, return_type };
+        let returns = self.block(body, Some(&function));
+        if let (Some(t), false) = (return_type, returns) {
+            let message = format!("{} can reach its end without returning a value of type {}", name, t);
+            self.diagnostics.push(Diagnostic::error(message, span));
+        }
+        self.levels.exit();
+    }
+
+    // True if every path through the block returns.
+    fn block(&mut self, body: &Vec<Box<Statement>>, function: Option<&Function>) -> bool {
+        let mut returns = false;
+        let mut warned = false;
+        for stmt in body {
+            // Only the first unreachable statement is reported.
+            if returns && !warned {
+                let span = self.span_of(stmt);
+                self.levels.report(Lint::UnreachableCode, String::from("unreachable statement"), span, &mut self.diagnostics);
+                warned = true;
+            }
+            returns |= self.stmt(stmt, function);
+        }
+        return returns;
+    }
+
+    fn stmt(&mut self, stmt: &Statement, function: Option<&Function>) -> bool {
+        match stmt {
+            Statement::ReturnStatement { span, value } => {
+                if let Some(v) = value {
+                    self.exp(*v);
+                }
+                let f = match function {
+                    Some(f) => f,
+                    None => return true,
+                };
+                match (f.return_type, value) {
+                    (Some(t), None) => {
+                        let message = format!("{} has to return a value of type {}", f.name, t);
+                        self.diagnostics.push(Diagnostic::error(message, *span));
+                    }
+                    (None, Some(_)) => {
+                        let message = format!("{} has no return type but returns a value", f.name);
+                        self.diagnostics.push(Diagnostic::error(message, *span));
+                    }
+                    _ => {}
+                }
+                true
+            }
+            Statement::IfElseStatement { condition, if_body, else_body } => {
+                self.exp(*condition);
+                let then_returns = self.block(if_body, function);
+                match else_body {
+                    Some(else_body) => self.block(else_body, function) && then_returns,
+                    None => false,
+                }
+            }
+            // The type checker made sure matches are exhaustive, so they return if every arm does.
+            Statement::MatchStatement { value, arms } => {
+                self.exp(*value);
+                let mut returns = !arms.is_empty();
+                for arm in arms {
+                    returns &= self.block(&arm.body, function);
+                }
+                returns
+            }
+            Statement::FuncStatement { name, attributes, return_type, body, span, .. } => {
+                self.function(name, attributes, return_type, body, *span);
+                false
+            }
+            Statement::VarStatement { value, .. } => {
+                if let Some(v) = value {
+                    self.exp(*v);
+                }
+                false
+            }
+            Statement::DestructureStatement { value, .. } | Statement::ExpressionStatement(value) => {
+                self.exp(*value);
+                false
+            }
+            Statement::EnumStatement { .. } | Statement::StructStatement { .. } |
+            Statement::ExternStatement { .. } | Statement::ImportStatement { .. } => false,
+        }
+    }
+
+    // Looks for lambdas, their bodies are checked like functions.
+    fn exp(&mut self, exp: ExpRef) {
+        match &*self.program[exp] {
+            Expression::Lambda { return_type, body, .. } => {
+                self.function("lambda", &[], return_type, body, self.spans[exp]);
+            }
+            Expression::FunctionCall { left, parameters } => {
+                self.exp(*left);
+                for p in parameters {
+                    self.exp(*p);
+                }
+            }
+            Expression::InfixExpression { left, right, .. } | Expression::AssignExpression { left, right } |
+            Expression::Index { left, index: right } => {
+                self.exp(*left);
+                self.exp(*right);
+            }
+            Expression::PrefixExpression { right, .. } | Expression::FieldAccess { left: right, .. } => {
+                self.exp(*right);
+            }
+            Expression::Slice { left, start, end } => {
+                self.exp(*left);
+                for e in start.iter().chain(end.iter()) {
+                    self.exp(*e);
+                }
+            }
+            Expression::Tuple(elements) => {
+                for e in elements {
+                    self.exp(*e);
+                }
+            }
+            Expression::Integer(_) | Expression::Char(_) | Expression::String(_) |
+            Expression::Identifier { .. } | Expression::GenericInstance { .. } => {}
+        }
+    }
+
+    fn span_of(&self, stmt: &Statement) -> Span {
+        match stmt {
+            Statement::FuncStatement { span, .. } | Statement::VarStatement { span, .. } |
+            Statement::DestructureStatement { span, .. } | Statement::ReturnStatement { span, .. } |
+            Statement::EnumStatement { span, .. } | Statement::StructStatement { span, .. } |
+            Statement::ExternStatement { span, .. } => *span,
+            Statement::IfElseStatement { condition: e, .. } | Statement::MatchStatement { value: e, .. } |
+            Statement::ExpressionStatement(e) => self.spans[*e],
+            Statement::ImportStatement { .. } => Span::default(),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// printf format strings as used by print and println.
+
+// What a conversion prints. There are no floats, so nothing fits %f.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Conversion {
+    Int,
+    Char,
+    String,
+    Float,
+}
+
+impl std::fmt::Display for Conversion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Conversion::Int => write!(f, "an int"),
+            Conversion::Char => write!(f, "a char"),
+            Conversion::String => write!(f, "a string"),
+            Conversion::Float => write!(f, "a float"),
+        }
+    }
+}
+
+pub enum FormatError {
+    // The string ends in the middle of a conversion, "50%".
+    Unterminated,
+    Unknown(char),
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::Unterminated => write!(f, "format string ends in the middle of a conversion"),
+            FormatError::Unknown(c) => write!(f, "unknown conversion %{} in format string", c),
+        }
+    }
+}
+
+// The conversions of a format string with their letters, "%5d and %s %%" gives
+// [('d', Int), ('s', String)].
+pub fn parse_format(fmt: &str) -> Result<Vec<(char, Conversion)>, FormatError> {
+    let mut conversions = Vec::new();
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        let letter = match chars.by_ref().find(|c| !"-+ #0123456789.l".contains(*c)) {
+            Some(l) => l,
+            None => return Err(FormatError::Unterminated),
+        };
+        let conversion = match letter {
+            '%' => continue,
+            'd' | 'i' | 'u' | 'x' | 'X' => Conversion::Int,
+            'c' => Conversion::Char,
+            's' => Conversion::String,
+            'f' => Conversion::Float,
+            _ => return Err(FormatError::Unknown(letter)),
+        };
+        conversions.push((letter, conversion));
+    }
+    return Ok(conversions);
+}
@@ -0,0 +1,409 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::token::{Span, TokenType};
+
+// Warnings about code that is allowed but most likely a mistake. The code of a
+// lint is the name it is reported with and that selects it in -A/-W/-D and in
+// @allow/@warn/@deny.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Lint {
+    UnusedVariables,
+    UnusedParameters,
+    UnusedFunctions,
+    ShadowedVariables,
+    ConstantCondition,
+    AlwaysTrueComparison,
+    UnreachableCode,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 7] = [
+        Lint::UnusedVariables,
+        Lint::UnusedParameters,
+        Lint::UnusedFunctions,
+        Lint::ShadowedVariables,
+        Lint::ConstantCondition,
+        Lint::AlwaysTrueComparison,
+        Lint::UnreachableCode,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedParameters => "unused_parameters",
+            Lint::UnusedFunctions => "unused_functions",
+            Lint::ShadowedVariables => "shadowed_variables",
+            Lint::ConstantCondition => "constant_condition",
+            Lint::AlwaysTrueComparison => "always_true_comparison",
+            Lint::UnreachableCode => "unreachable_code",
+        }
+    }
+
+    // The lints a name selects, `warnings` selects all of them.
+    pub fn parse(name: &str) -> Option<Vec<Lint>> {
+        if name == "warnings" {
+            return Some(Lint::ALL.to_vec());
+        }
+        return Lint::ALL.iter().find(|l| l.code() == name).map(|l| vec![*l]);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Level {
+    Allow,
+    Warn,
+    // Reported as an error.
+    Deny,
+}
+
+impl Level {
+    fn from_attribute(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+// The levels given on the command line, every lint warns by default.
+pub struct LintLevels {
+    levels: HashMap<Lint, Level>,
+}
+
+impl LintLevels {
+    pub fn new() -> Self {
+        return LintLevels {
+            levels: Lint::ALL.iter().map(|l| (*l, Level::Warn)).collect(),
+        };
+    }
+
+    // False if the name isn't a lint.
+    pub fn set(&mut self, name: &str, level: Level) -> bool {
+        match Lint::parse(name) {
+            Some(lints) => {
+                for lint in lints {
+                    self.levels.insert(lint, level);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// The levels at some point of the program: the command line levels overridden
+// by the attributes of the functions around it, the innermost one wins.
+pub(super) struct Levels<'l> {
+    base: &'l LintLevels,
+    scopes: Vec<HashMap<Lint, Level>>,
+}
+
+impl<'l> Levels<'l> {
+    pub(super) fn new(base: &'l LintLevels) -> Self {
+        return Levels { base, scopes: Vec::new() };
+    }
+
+    // Unknown attributes and lints are reported by check_lints.
+    pub(super) fn enter(&mut self, attributes: &[Attribute]) {
+        let mut scope = HashMap::new();
+        for attribute in attributes {
+            if let Some(level) = Level::from_attribute(&attribute.name) {
+                for lint in attribute.args.iter().filter_map(|a| Lint::parse(a)).flatten() {
+                    scope.insert(lint, level);
+                }
+            }
+        }
+        self.scopes.push(scope);
+    }
+
+    pub(super) fn exit(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub(super) fn report(&self, lint: Lint, message: String, span: Span, out: &mut Vec<Diagnostic>) {
+        let level = self.scopes.iter().rev().find_map(|s| s.get(&lint)).unwrap_or(&self.base.levels[&lint]);
+        let severity = match level {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+        out.push(Diagnostic { severity, code: Some(lint.code()), message, span });
+    }
+}
+
+// Runs every lint except unreachable_code, which is part of check_flow.
+// Variables and parameters starting with _ are never unused.
+pub fn check_lints(stmts: &[&Statement], program: &Program, spans: &Vec<Span>, levels: &LintLevels) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        program,
+        spans,
+        levels: Levels::new(levels),
+        scopes: Vec::new(),
+        used: HashSet::new(),
+        function: None,
+        diagnostics: Vec::new(),
+    };
+    for stmt in stmts {
+        if let Statement::FuncStatement { name, .. } = stmt {
+            linter.function = Some(name.clone());
+        }
+        linter.stmt(stmt);
+    }
+    for stmt in stmts {
+        if let Statement::FuncStatement { name, public: false, span, attributes, .. } = stmt {
+            if name != "main" && !linter.used.contains(name) {
+                linter.levels.enter(attributes);
+                linter.report(Lint::UnusedFunctions, format!("function {} is never used", name), *span);
+                linter.levels.exit();
+            }
+        }
+    }
+    return linter.diagnostics;
+}
+
+struct Local {
+    name: String,
+    span: Span,
+    // UnusedVariables or UnusedParameters
+    lint: Lint,
+    used: bool,
+}
+
+struct Linter<'a> {
+    program: &'a Program,
+    spans: &'a Vec<Span>,
+    levels: Levels<'a>,
+    // Locals of the function being checked, a lambda continues the scopes of
+    // the function around it.
+    scopes: Vec<Vec<Local>>,
+    // Top level names used outside of their own body.
+    used: HashSet<String>,
+    // The top level function being checked.
+    function: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, lint: Lint, message: String, span: Span) {
+        self.levels.report(lint, message, span, &mut self.diagnostics);
+    }
+
+    fn check_attributes(&mut self, attributes: &[Attribute]) {
+        let hints: Vec<&Attribute> = attributes.iter().filter(|a| a.name == "inline" || a.name == "noinline").collect();
+        if hints.len() > 1 {
+            self.diagnostics.push(Diagnostic::error(String::from("only one of @inline and @noinline can be given"), hints[1].span));
+        }
+        for attribute in attributes {
+            if attribute.name == "inline" || attribute.name == "noinline" {
+                if !attribute.args.is_empty() {
+                    self.diagnostics.push(Diagnostic::error(format!("@{} takes no arguments", attribute.name), attribute.span));
+                }
+                continue;
+            }
+            if Level::from_attribute(&attribute.name).is_none() {
+                let message = format!("unknown attribute @{}", attribute.name);
+                self.diagnostics.push(Diagnostic::error(message, attribute.span));
+                continue;
+            }
+            for arg in &attribute.args {
+                if Lint::parse(arg).is_none() {
+                    self.diagnostics.push(Diagnostic::error(format!("unknown lint {}", arg), attribute.span));
+                }
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &String, span: Span, lint: Lint) {
+        // The resolver rejects variables at the top level, they have no scope here.
+        if self.scopes.is_empty() {
+            return;
+        }
+        if self.scopes.iter().flatten().any(|l| l.name == *name) {
+            self.report(Lint::ShadowedVariables, format!("{} shadows a variable of an outer scope", name), span);
+        }
+        self.scopes.last_mut().unwrap().push(Local { name: name.clone(), span, lint, used: false });
+    }
+
+    fn pop_scope(&mut self) {
+        for local in self.scopes.pop().unwrap() {
+            if local.used || local.name.starts_with('_') {
+                continue;
+            }
+            let message = match local.lint {
+                Lint::UnusedParameters => format!("unused parameter {}", local.name),
+                _ => format!("unused variable {}", local.name),
+            };
+            self.report(local.lint, message, local.span);
+        }
+    }
+
+    fn function(&mut self, attributes: &[Attribute], call_inputs: &Vec<Parameter>, body: &Vec<Box<Statement>>) {
+        self.check_attributes(attributes);
+        self.levels.enter(attributes);
+        self.scopes.push(Vec::new());
+        for param in call_inputs {
+            self.declare(&param.name, param.span, Lint::UnusedParameters);
+        }
+        for stmt in body {
+            self.stmt(stmt);
+        }
+        self.pop_scope();
+        self.levels.exit();
+    }
+
+    fn block(&mut self, body: &Vec<Box<Statement>>) {
+        self.scopes.push(Vec::new());
+        for stmt in body {
+            self.stmt(stmt);
+        }
+        self.pop_scope();
+    }
+
+    fn stmt(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::IfElseStatement { condition, if_body, else_body } => {
+                self.exp(*condition);
+                if let Some(value) = self.constant(*condition) {
+                    let message = format!("condition is always {}", value != 0);
+                    self.report(Lint::ConstantCondition, message, self.spans[*condition]);
+                }
+                self.block(if_body);
+                if let Some(else_body) = else_body {
+                    self.block(else_body);
+                }
+            }
+            Statement::FuncStatement { attributes, call_inputs, body, .. } => {
+                self.function(attributes, call_inputs, body);
+            }
+            Statement::VarStatement { name, span, value, .. } => {
+                if let Some(v) = value {
+                    self.exp(*v);
+                }
+                self.declare(name, *span, Lint::UnusedVariables);
+            }
+            Statement::DestructureStatement { names, span, value } => {
+                self.exp(*value);
+                for name in names {
+                    self.declare(name, *span, Lint::UnusedVariables);
+                }
+            }
+            Statement::ReturnStatement { value: Some(value), .. } | Statement::ExpressionStatement(value) => {
+                self.exp(*value);
+            }
+            Statement::MatchStatement { value, arms } => {
+                self.exp(*value);
+                for arm in arms {
+                    self.block(&arm.body);
+                }
+            }
+            Statement::ReturnStatement { value: None, .. } | Statement::EnumStatement { .. } |
+            Statement::StructStatement { .. } | Statement::ExternStatement { .. } |
+            Statement::ImportStatement { .. } => {}
+        }
+    }
+
+    fn use_global(&mut self, name: &String) {
+        if self.function.as_ref() != Some(name) {
+            self.used.insert(name.clone());
+        }
+    }
+
+    fn exp(&mut self, exp: ExpRef) {
+        match &*self.program[exp] {
+            Expression::Identifier { value, decl, .. } => match decl {
+                Declaration::Local(_) => {
+                    if let Some(local) = self.scopes.iter_mut().flatten().rev().find(|l| l.name == *value) {
+                        local.used = true;
+                    }
+                }
+                Declaration::Global(_) => self.use_global(value),
+                Declaration::Builtin | Declaration::Unresolved => {}
+            },
+            Expression::GenericInstance { value, .. } => self.use_global(value),
+            Expression::InfixExpression { left, op, right } => {
+                self.exp(*left);
+                self.exp(*right);
+                let reflexive = matches!(op, TokenType::EQ | TokenType::LTEQ | TokenType::GTEQ);
+                if reflexive && self.constant(*left).is_none() && self.same(*left, *right) {
+                    self.report(Lint::AlwaysTrueComparison, String::from("comparison is always true"), self.spans[exp]);
+                }
+            }
+            Expression::Lambda { call_inputs, body, .. } => {
+                self.function(&[], call_inputs, body);
+            }
+            Expression::FunctionCall { left, parameters } => {
+                self.exp(*left);
+                for p in parameters {
+                    self.exp(*p);
+                }
+            }
+            Expression::AssignExpression { left, right } | Expression::Index { left, index: right } => {
+                self.exp(*left);
+                self.exp(*right);
+            }
+            Expression::PrefixExpression { right, .. } | Expression::FieldAccess { left: right, .. } => {
+                self.exp(*right);
+            }
+            Expression::Slice { left, start, end } => {
+                self.exp(*left);
+                for e in start.iter().chain(end.iter()) {
+                    self.exp(*e);
+                }
+            }
+            Expression::Tuple(elements) => {
+                for e in elements {
+                    self.exp(*e);
+                }
+            }
+            Expression::Integer(_) | Expression::Char(_) | Expression::String(_) => {}
+        }
+    }
+
+    // The value of an expression made of literals, comparisons are 0 or 1.
+    fn constant(&self, exp: ExpRef) -> Option<i64> {
+        match &*self.program[exp] {
+            Expression::Integer(i) => Some(*i),
+            Expression::Char(c) => Some(*c as i64),
+            Expression::PrefixExpression { op: TokenType::Minus, right } => self.constant(*right)?.checked_neg(),
+            Expression::InfixExpression { left, op, right } => {
+                let (l, r) = (self.constant(*left)?, self.constant(*right)?);
+                match op {
+                    TokenType::Plus => l.checked_add(r),
+                    TokenType::Minus => l.checked_sub(r),
+                    TokenType::Astrik => l.checked_mul(r),
+                    TokenType::Slash => l.checked_div(r),
+                    TokenType::EQ => Some((l == r) as i64),
+                    TokenType::NotEQ => Some((l != r) as i64),
+                    TokenType::LT => Some((l < r) as i64),
+                    TokenType::LTEQ => Some((l <= r) as i64),
+                    TokenType::GT => Some((l > r) as i64),
+                    TokenType::GTEQ => Some((l >= r) as i64),
+                    TokenType::And => Some((l != 0 && r != 0) as i64),
+                    TokenType::Or => Some((l != 0 || r != 0) as i64),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // Whether two expressions without side effects read the same value.
+    fn same(&self, a: ExpRef, b: ExpRef) -> bool {
+        match (&*self.program[a], &*self.program[b]) {
+            (Expression::Identifier { value: x, .. }, Expression::Identifier { value: y, .. }) => x == y,
+            (Expression::FieldAccess { left: l1, field: f1 }, Expression::FieldAccess { left: l2, field: f2 }) => {
+                f1 == f2 && self.same(*l1, *l2)
+            }
+            (Expression::Index { left: l1, index: i1 }, Expression::Index { left: l2, index: i2 }) => {
+                self.same(*l1, *l2) && self.same(*i1, *i2)
+            }
+            (Expression::Integer(x), Expression::Integer(y)) => x == y,
+            (Expression::Char(x), Expression::Char(y)) => x == y,
+            _ => false,
+        }
+    }
+}
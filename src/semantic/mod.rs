@@ -0,0 +1,34 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+
+mod flow;
+pub(crate) mod format;
+mod lint;
+mod resolve;
+mod typeck;
+pub use flow::check_flow;
+pub use lint::{check_lints, Level, LintLevels};
+pub use resolve::resolve_names;
+pub use typeck::{check_types, ExpTypes};
+
+pub type EnumTable = HashMap<String, Vec<String>>;
+
+pub fn collect_enums<'a>(stmts: impl IntoIterator<Item = &'a Statement>) -> EnumTable {
+    let mut enums: EnumTable = HashMap::new();
+    for stmt in stmts {
+        if let Statement::EnumStatement { name, variants, .. } = stmt {
+            if enums.contains_key(name) {
+                panic!("Enum {} is defined twice", name);
+            }
+            let mut seen = HashSet::new();
+            for v in variants {
+                if !seen.insert(v) {
+                    panic!("Variant {}.{} is defined twice", name, v);
+                }
+            }
+            enums.insert(name.clone(), variants.clone());
+        }
+    }
+    return enums;
+}
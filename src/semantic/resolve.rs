@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::diagnostics::Diagnostic;
+use crate::token::Span;
+
+pub enum NameErrorKind {
+    Undefined(String),
+    Duplicate(String),
+    GlobalVariable(String),
+}
+
+impl std::fmt::Display for NameErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameErrorKind::Undefined(name) => write!(f, "cannot find {} in this scope", name),
+            NameErrorKind::Duplicate(name) => write!(f, "{} is defined more than once", name),
+            NameErrorKind::GlobalVariable(name) => write!(f, "{} is declared outside of a function, there are no global variables", name),
+        }
+    }
+}
+
+pub struct NameError {
+    pub kind: NameErrorKind,
+    pub span: Span,
+}
+
+impl NameError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        return Diagnostic::error(self.kind.to_string(), self.span);
+    }
+}
+
+const BUILTINS: [&str; 5] = ["print", "println", "len", "int", "char"];
+
+// Resolves every identifier to the variable or declaration it refers to and
+// stores that in the identifier. Blocks get their own scope and may shadow
+// names of the scopes around them, but a name can only be declared once per
+// scope.
+pub fn resolve_names(stmts: &[&Statement], program: &mut Program, spans: &Vec<Span>) -> Vec<NameError> {
+    let mut resolver = Resolver {
+        program,
+        spans,
+        globals: HashMap::new(),
+        scopes: vec![HashMap::new()],
+        errors: Vec::new(),
+    };
+    resolver.collect(stmts);
+    for stmt in stmts {
+        resolver.stmt(stmt);
+    }
+    return resolver.errors;
+}
+
+struct Resolver<'a> {
+    program: &'a mut Program,
+    spans: &'a Vec<Span>,
+    globals: HashMap<String, Declaration>,
+    scopes: Vec<HashMap<String, Span>>,
+    errors: Vec<NameError>,
+}
+
+impl<'a> Resolver<'a> {
+    // Top level declarations can be used before they are defined. Builtins can
+    // be replaced by functions of the same name. Variables can only be
+    // declared inside functions.
+    fn collect(&mut self, stmts: &[&Statement]) {
+        for name in BUILTINS.iter().chain(["printf"].iter()) {
+            self.globals.insert(name.to_string(), Declaration::Builtin);
+        }
+        for stmt in stmts {
+            let (name, span) = match stmt {
+                Statement::FuncStatement { name, span, .. } |
+                Statement::ExternStatement { name, span, .. } |
+                Statement::StructStatement { name, span, .. } |
+                Statement::EnumStatement { name, span, .. } => (name, *span),
+                Statement::VarStatement { name, span, .. } => {
+                    self.error(NameErrorKind::GlobalVariable(name.clone()), *span);
+                    continue;
+                }
+                Statement::DestructureStatement { names, span, .. } => {
+                    for name in names {
+                        self.error(NameErrorKind::GlobalVariable(name.clone()), *span);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Some(Declaration::Global(_)) = self.globals.get(name) {
+                self.error(NameErrorKind::Duplicate(name.clone()), span);
+                continue;
+            }
+            self.globals.insert(name.clone(), Declaration::Global(span));
+        }
+    }
+
+    fn error(&mut self, kind: NameErrorKind, span: Span) {
+        self.errors.push(NameError { kind, span });
+    }
+
+    fn declare(&mut self, name: &String, span: Span) {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.contains_key(name) {
+            self.error(NameErrorKind::Duplicate(name.clone()), span);
+            return;
+        }
+        scope.insert(name.clone(), span);
+    }
+
+    fn lookup(&self, name: &String) -> Option<Declaration> {
+        if let Some(span) = self.scopes.iter().rev().find_map(|s| s.get(name)) {
+            return Some(Declaration::Local(*span));
+        }
+        return self.globals.get(name).copied();
+    }
+
+    fn block(&mut self, body: &Vec<Box<Statement>>) {
+        self.scopes.push(HashMap::new());
+        for stmt in body {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    // Parameters and the locals of the body share one scope.
+    fn function(&mut self, call_inputs: &Vec<Parameter>, body: &Vec<Box<Statement>>) {
+        self.scopes.push(HashMap::new());
+        for param in call_inputs {
+            self.declare(&param.name, param.span);
+        }
+        for stmt in body {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::IfElseStatement { condition, if_body, else_body } => {
+                self.exp(*condition);
+                self.block(if_body);
+                if let Some(else_body) = else_body {
+                    self.block(else_body);
+                }
+            }
+            Statement::FuncStatement { call_inputs, body, .. } => {
+                self.function(call_inputs, body);
+            }
+            Statement::VarStatement { name, span, value, .. } => {
+                if let Some(v) = value {
+                    self.exp(*v);
+                }
+                self.declare(name, *span);
+            }
+            Statement::DestructureStatement { names, span, value } => {
+                self.exp(*value);
+                for name in names {
+                    self.declare(name, *span);
+                }
+            }
+            Statement::ReturnStatement { value: Some(value), .. } | Statement::ExpressionStatement(value) => {
+                self.exp(*value);
+            }
+            Statement::ReturnStatement { value: None, .. } => {}
+            Statement::MatchStatement { value, arms } => {
+                self.exp(*value);
+                for arm in arms {
+                    self.block(&arm.body);
+                }
+            }
+            Statement::EnumStatement { .. } | Statement::StructStatement { .. } |
+            Statement::ExternStatement { .. } | Statement::ImportStatement { .. } => {}
+        }
+    }
+
+    fn exp(&mut self, exp: ExpRef) {
+        match *self.program[exp].clone() {
+            Expression::Identifier { value, ident_type, .. } => {
+                let decl = match self.lookup(&value) {
+                    Some(decl) => decl,
+                    None => {
+                        self.error(NameErrorKind::Undefined(value.clone()), self.spans[exp]);
+                        Declaration::Unresolved
+                    }
+                };
+                self.program[exp] = Box::new(Expression::Identifier { value, ident_type, decl });
+            }
+            Expression::GenericInstance { value, .. } => {
+                if !self.globals.contains_key(&value) {
+                    self.error(NameErrorKind::Undefined(value), self.spans[exp]);
+                }
+            }
+            Expression::FunctionCall { left, parameters } => {
+                self.exp(left);
+                for p in parameters {
+                    self.exp(p);
+                }
+            }
+            Expression::InfixExpression { left, right, .. } | Expression::AssignExpression { left, right } => {
+                self.exp(left);
+                self.exp(right);
+            }
+            Expression::PrefixExpression { right, .. } => self.exp(right),
+            Expression::FieldAccess { left, .. } => self.exp(left),
+            Expression::Index { left, index } => {
+                self.exp(left);
+                self.exp(index);
+            }
+            Expression::Slice { left, start, end } => {
+                self.exp(left);
+                for e in start.into_iter().chain(end) {
+                    self.exp(e);
+                }
+            }
+            Expression::Tuple(elements) => {
+                for e in elements {
+                    self.exp(e);
+                }
+            }
+            Expression::Lambda { call_inputs, body, .. } => {
+                self.function(&call_inputs, &body);
+            }
+            Expression::Integer(_) | Expression::Char(_) | Expression::String(_) => {}
+        }
+    }
+}
@@ -0,0 +1,918 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+use crate::compiler::types::*;
+use crate::diagnostics::Diagnostic;
+use crate::token::{Span, TokenType};
+
+use super::format::{parse_format, Conversion, FormatError};
+use super::{collect_enums, EnumTable};
+
+pub enum TypeErrorKind {
+    Mismatch { expected: Type, found: Type },
+    UnknownType(String),
+    Operator { op: TokenType, left: Type, right: Type },
+    PrefixOperator { op: TokenType, operand: Type },
+    Arguments { callee: String, expected: usize, found: usize },
+    TypeArguments { name: String, expected: usize, found: usize },
+    CantInfer { param: String, name: String },
+    MissingTypeArguments(String),
+    ExternValue(String),
+    ExternType { name: String, found: Type },
+    NotCallable(Type),
+    NoField { on: String, field: String },
+    NotAssignable,
+    Destructure { found: Type, names: usize },
+    Untyped(String),
+    CantInferVar { name: String, found: Type },
+    Format(FormatError),
+    FormatArguments { callee: String, expected: usize, found: usize },
+    FormatMismatch { letter: char, expected: Conversion, found: Type },
+    MixedMatch { first: String, second: String },
+    DuplicateArm(String),
+    UnreachableArm,
+    EmptyMatch,
+    NonExhaustive(Vec<String>),
+    // An error in the body of a generic function with its type arguments in place.
+    Instance { name: String, error: Box<TypeErrorKind> },
+}
+
+impl std::fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeErrorKind::Mismatch { expected, found } => {
+                write!(f, "mismatched types: expected {}, found {}", expected, found)
+            }
+            TypeErrorKind::UnknownType(t) => write!(f, "unknown type {}", t),
+            TypeErrorKind::Operator { op, left, right } => {
+                write!(f, "operator {:?} can't be used on {} and {}", op, left, right)
+            }
+            TypeErrorKind::PrefixOperator { op, operand } => {
+                write!(f, "operator {:?} can't be used on {}", op, operand)
+            }
+            TypeErrorKind::Arguments { callee, expected, found } => {
+                write!(f, "{} takes {} arguments but got {}", callee, expected, found)
+            }
+            TypeErrorKind::TypeArguments { name, expected, found } => {
+                write!(f, "{} takes {} type arguments but got {}", name, expected, found)
+            }
+            TypeErrorKind::CantInfer { param, name } => {
+                write!(f, "can't infer type parameter {} of {}", param, name)
+            }
+            TypeErrorKind::MissingTypeArguments(name) => {
+                write!(f, "generic function {} needs type arguments to be used as a value", name)
+            }
+            TypeErrorKind::ExternValue(name) => {
+                write!(f, "extern function {} can only be called, it can't be used as a value", name)
+            }
+            TypeErrorKind::ExternType { name, found } => {
+                write!(f, "a value of type {} doesn't fit into a register, extern function {} can't take or return it", found, name)
+            }
+            TypeErrorKind::NotCallable(t) => write!(f, "a value of type {} can't be called", t),
+            TypeErrorKind::NoField { on, field } => write!(f, "{} has no field {}", on, field),
+            TypeErrorKind::NotAssignable => write!(f, "only variables can be assigned to"),
+            TypeErrorKind::Destructure { found, names } => {
+                write!(f, "can't destructure {} into {} variables", found, names)
+            }
+            TypeErrorKind::Untyped(name) => write!(f, "variable {} needs a type or a value", name),
+            TypeErrorKind::CantInferVar { name, found } => {
+                write!(f, "can't infer the type of {} from a value of type {}", name, found)
+            }
+            TypeErrorKind::Format(e) => write!(f, "{}", e),
+            TypeErrorKind::FormatArguments { callee, expected, found } => {
+                write!(f, "the format string of {} takes {} arguments but got {}", callee, expected, found)
+            }
+            TypeErrorKind::FormatMismatch { letter, expected, found } => {
+                write!(f, "%{} expects {}, found {}", letter, expected, found)
+            }
+            TypeErrorKind::MixedMatch { first, second } => write!(f, "match mixes variants of {} and {}", first, second),
+            TypeErrorKind::DuplicateArm(variant) => write!(f, "variant {} is matched twice", variant),
+            TypeErrorKind::UnreachableArm => write!(f, "unreachable match arm after _"),
+            TypeErrorKind::EmptyMatch => write!(f, "match statement without any arms"),
+            TypeErrorKind::NonExhaustive(missing) => write!(f, "match is not exhaustive, missing: {}", missing.join(", ")),
+            TypeErrorKind::Instance { name, error } => write!(f, "{} in {}", error, name),
+        }
+    }
+}
+
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl TypeError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        return Diagnostic::error(self.kind.to_string(), self.span);
+    }
+}
+
+struct FunctionSig {
+    type_params: Vec<String>,
+    params: Vec<Type>,
+    return_type: Option<Type>,
+    variadic: bool,
+    // Extern functions can only be called, there is no closure for them.
+    external: bool,
+}
+
+// The inferred type of every expression, the compiler reads it instead of
+// working types out on its own. Types in generic functions can contain their
+// type parameters.
+pub type ExpTypes = HashMap<ExpRef, Type>;
+
+// Infers the type of every expression and checks it against where it is used.
+// Type errors don't stop the check, the expression gets the type any instead
+// so one mistake doesn't cause a chain of errors.
+// Variables declared without a type get the inferred type written into their
+// statement, so the compiler only sees typed declarations.
+pub fn check_types(mut stmts: Vec<&mut Statement>, program: &mut Program, spans: &Vec<Span>) -> (Vec<TypeError>, ExpTypes) {
+    let mut checker = TypeChecker {
+        program,
+        spans,
+        enums: collect_enums(stmts.iter().map(|s| &**s)),
+        functions: HashMap::new(),
+        structs: HashMap::new(),
+        scopes: vec![HashMap::new()],
+        type_params: Vec::new(),
+        return_types: Vec::new(),
+        errors: Vec::new(),
+        types: HashMap::new(),
+        bindings: HashMap::new(),
+        instances: Vec::new(),
+    };
+    checker.collect(&stmts);
+    for stmt in stmts.iter_mut() {
+        checker.stmt(stmt);
+    }
+    // Errors in generic bodies would be reported once per instance.
+    if checker.errors.is_empty() {
+        checker.check_instances(&mut stmts);
+    }
+    return (checker.errors, checker.types);
+}
+
+struct TypeChecker<'a> {
+    program: &'a mut Program,
+    spans: &'a Vec<Span>,
+    enums: EnumTable,
+    functions: HashMap<String, FunctionSig>,
+    structs: HashMap<String, StructDef>,
+    scopes: Vec<HashMap<String, Type>>,
+    type_params: Vec<String>,
+    // Return types of the functions and lambdas we are in, innermost last.
+    return_types: Vec<Option<Type>>,
+    errors: Vec<TypeError>,
+    types: ExpTypes,
+    // The type arguments of the instance of a generic function being checked.
+    bindings: HashMap<String, Type>,
+    // Generic functions and the type arguments they are used with.
+    instances: Vec<(String, Vec<Type>)>,
+}
+
+impl<'a> TypeChecker<'a> {
+    fn collect(&mut self, stmts: &Vec<&mut Statement>) {
+        for stmt in stmts {
+            match stmt {
+                Statement::FuncStatement { name, type_params, call_inputs, return_type, .. } => {
+                    self.functions.insert(name.clone(), FunctionSig {
+                        type_params: type_params.clone(),
+                        params: call_inputs.iter().map(|p| p.param_type.clone()).collect(),
+                        return_type: return_type.clone(),
+                        variadic: false,
+                        external: false,
+                    });
+                }
+                Statement::ExternStatement { name, call_inputs, return_type, variadic, .. } => {
+                    self.functions.insert(name.clone(), FunctionSig {
+                        type_params: Vec::new(),
+                        params: call_inputs.iter().map(|p| p.param_type.clone()).collect(),
+                        return_type: return_type.clone(),
+                        variadic: *variadic,
+                        external: true,
+                    });
+                }
+                Statement::StructStatement { name, type_params, fields, .. } => {
+                    self.structs.insert(name.clone(), StructDef {
+                        type_params: type_params.clone(),
+                        fields: fields.iter().map(|f| (f.name.clone(), f.param_type.clone())).collect(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        if !self.functions.contains_key("printf") {
+            self.functions.insert(String::from("printf"), FunctionSig {
+                type_params: Vec::new(),
+                params: vec![Type::String],
+                return_type: Some(Type::Int),
+                variadic: true,
+                external: true,
+            });
+        }
+    }
+
+    fn error(&mut self, kind: TypeErrorKind, span: Span) -> Type {
+        self.errors.push(TypeError { kind, span });
+        return Type::Any;
+    }
+
+    fn lookup(&self, name: &String) -> Option<&Type> {
+        return self.scopes.iter().rev().find_map(|s| s.get(name));
+    }
+
+    fn declare(&mut self, name: &String, t: Type) {
+        self.scopes.last_mut().unwrap().insert(name.clone(), t);
+    }
+
+    // The type with the type arguments of the checked instance in place.
+    fn bind(&self, t: &Type) -> Type {
+        return substitute(t, &self.bindings);
+    }
+
+    fn is_enum(&self, t: &Type) -> bool {
+        return matches!(t, Type::Named(name) if self.enums.contains_key(name));
+    }
+
+    fn is_type_param(&self, t: &Type) -> bool {
+        return matches!(t, Type::Named(name) if self.type_params.contains(name));
+    }
+
+    // Operators on type parameters are checked again for every instance of
+    // the function, so they are allowed here.
+    fn is_numeric(&self, t: &Type) -> bool {
+        return matches!(t, Type::Int | Type::Char | Type::Any) || self.is_enum(t) || self.is_type_param(t);
+    }
+
+    // Enum values are ints, so both can be used for each other. Type
+    // parameters fit anything, generic bodies are checked again with the
+    // type arguments of every instance. Arrays are laid out like tuples, so
+    // a tuple of the right elements fits an array.
+    fn fits(&self, expected: &Type, found: &Type) -> bool {
+        if expected == found || *expected == Type::Any || *found == Type::Any {
+            return true;
+        }
+        if self.is_type_param(expected) || self.is_type_param(found) {
+            return true;
+        }
+        let all = |e: &[Type], f: &[Type]| e.len() == f.len() && e.iter().zip(f).all(|(e, f)| self.fits(e, f));
+        match (expected, found) {
+            (Type::Named(_), Type::Int) => self.is_enum(expected),
+            (Type::Int, Type::Named(_)) => self.is_enum(found),
+            (Type::Pointer(e), Type::Pointer(f)) => self.fits(e, f),
+            (Type::Array(e, n), Type::Array(f, m)) => n == m && self.fits(e, f),
+            (Type::Array(e, n), Type::Tuple(f)) => *n == f.len() && f.iter().all(|f| self.fits(e, f)),
+            (Type::Tuple(e), Type::Tuple(f)) => all(e, f),
+            (Type::Function { params: ep, return_type: er }, Type::Function { params: fp, return_type: fr }) => {
+                self.fits(er, fr) && all(ep, fp)
+            }
+            (Type::Generic { name: en, args: ea }, Type::Generic { name: fname, args: fa }) => en == fname && all(ea, fa),
+            _ => false,
+        }
+    }
+
+    // The number of registers a value of this type takes, like the compiler
+    // lays it out. Extern functions only pass values of one register.
+    fn slots(&self, t: &Type) -> usize {
+        if let Some(fields) = aggregate_fields(t, &self.structs) {
+            return fields.iter().map(|(_, f)| self.slots(f)).sum();
+        }
+        return if *t == Type::Void { 0 } else { 1 };
+    }
+
+    // Reports every type name in `t` that doesn't exist.
+    fn check_type(&mut self, t: &Type, span: Span) {
+        let (name, args) = match t {
+            Type::Named(name) => (name, &Vec::new()),
+            Type::Generic { name, args } => (name, args),
+            _ => {
+                t.map(|inner| {
+                    self.check_type(inner, span);
+                    inner.clone()
+                });
+                return;
+            }
+        };
+        for a in args {
+            self.check_type(a, span);
+        }
+        if let Some(s) = self.structs.get(name) {
+            if s.type_params.len() != args.len() {
+                let expected = s.type_params.len();
+                self.error(TypeErrorKind::TypeArguments { name: name.clone(), expected, found: args.len() }, span);
+            }
+            return;
+        }
+        let known = self.type_params.contains(name) || self.enums.contains_key(name);
+        if !known || !args.is_empty() {
+            self.error(TypeErrorKind::UnknownType(name.clone()), span);
+        }
+    }
+
+    fn expect(&mut self, exp: ExpRef, expected: &Type) {
+        let found = self.infer(exp);
+        if !self.fits(expected, &found) {
+            let expected = expected.clone();
+            self.error(TypeErrorKind::Mismatch { expected, found }, self.spans[exp]);
+        }
+    }
+
+    fn block(&mut self, body: &mut Vec<Box<Statement>>) {
+        self.scopes.push(HashMap::new());
+        for stmt in body.iter_mut() {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn function(&mut self, call_inputs: &Vec<Parameter>, return_type: &Option<Type>, body: &mut Vec<Box<Statement>>, span: Span) {
+        self.scopes.push(HashMap::new());
+        for param in call_inputs {
+            let t = self.bind(&param.param_type);
+            self.check_type(&t, span);
+            self.declare(&param.name, t);
+        }
+        let return_type = return_type.as_ref().map(|r| self.bind(r));
+        if let Some(r) = &return_type {
+            self.check_type(r, span);
+        }
+        self.return_types.push(return_type);
+        self.block(body);
+        self.return_types.pop();
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &mut Statement) {
+        match stmt {
+            Statement::IfElseStatement { condition, if_body, else_body } => {
+                let t = self.infer(*condition);
+                if !self.is_numeric(&t) {
+                    self.error(TypeErrorKind::Mismatch { expected: Type::Int, found: t }, self.spans[*condition]);
+                }
+                self.block(if_body);
+                if let Some(else_body) = else_body {
+                    self.block(else_body);
+                }
+            }
+            Statement::FuncStatement { type_params, call_inputs, return_type, body, span, .. } => {
+                let outer = std::mem::replace(&mut self.type_params, type_params.clone());
+                self.function(call_inputs, return_type, body, *span);
+                self.type_params = outer;
+            }
+            Statement::StructStatement { type_params, fields, span, .. } => {
+                let outer = std::mem::replace(&mut self.type_params, type_params.clone());
+                for field in fields {
+                    self.check_type(&field.param_type, *span);
+                }
+                self.type_params = outer;
+            }
+            Statement::ExternStatement { name, call_inputs, return_type, span, .. } => {
+                for t in call_inputs.iter().map(|p| &p.param_type).chain(return_type.iter()) {
+                    let errors = self.errors.len();
+                    self.check_type(t, *span);
+                    if self.errors.len() == errors && self.slots(t) != 1 {
+                        self.error(TypeErrorKind::ExternType { name: name.clone(), found: t.clone() }, *span);
+                    }
+                }
+            }
+            Statement::VarStatement { name, span, value, var_type: None } => {
+                let t = self.infer_var(name, value, *span);
+                self.declare(name, t.clone());
+                *stmt = Statement::VarStatement { name: name.clone(), span: *span, value: *value, var_type: Some(t) };
+            }
+            Statement::VarStatement { name, span, value, var_type: Some(var_type) } => {
+                let var_type = self.bind(var_type);
+                let errors = self.errors.len();
+                self.check_type(&var_type, *span);
+                if let Some(v) = value {
+                    if self.errors.len() == errors {
+                        self.expect(*v, &var_type);
+                    } else {
+                        self.infer(*v);
+                    }
+                }
+                self.declare(name, var_type);
+            }
+            Statement::DestructureStatement { names, span, value } => {
+                let t = self.infer(*value);
+                let fields = match aggregate_fields(&t, &self.structs) {
+                    Some(f) if f.len() == names.len() => f.into_iter().map(|(_, t)| t).collect(),
+                    _ => {
+                        if t != Type::Any {
+                            self.error(TypeErrorKind::Destructure { found: t, names: names.len() }, *span);
+                        }
+                        vec![Type::Any; names.len()]
+                    }
+                };
+                for (name, t) in names.iter().zip(fields) {
+                    self.declare(name, t);
+                }
+            }
+            // Missing or unexpected return values are reported by the flow check.
+            Statement::ReturnStatement { value: Some(value), .. } => {
+                match self.return_types.last() {
+                    Some(Some(r)) => {
+                        let r = r.clone();
+                        self.expect(*value, &r);
+                    }
+                    _ => {
+                        self.infer(*value);
+                    }
+                }
+            }
+            Statement::ReturnStatement { value: None, .. } => {}
+            Statement::MatchStatement { value, arms } => {
+                let t = self.infer(*value);
+                if let Some(e) = self.check_arms(arms, self.spans[*value]) {
+                    let e = Type::Named(e);
+                    if !self.fits(&e, &t) {
+                        self.error(TypeErrorKind::Mismatch { expected: e, found: t }, self.spans[*value]);
+                    }
+                }
+                for arm in arms.iter_mut() {
+                    self.block(&mut arm.body);
+                }
+            }
+            Statement::ExpressionStatement(exp) => {
+                self.infer(*exp);
+            }
+            Statement::EnumStatement { .. } | Statement::ImportStatement { .. } => {}
+        }
+    }
+
+    // Arms have to name variants of one known enum, each once, and cover all
+    // of them unless a wildcard comes last. Arms have no span of their own,
+    // errors point at the matched value. The enum matched on if it is known.
+    fn check_arms(&mut self, arms: &Vec<MatchArm>, span: Span) -> Option<String> {
+        let mut matched: Option<String> = None;
+        let mut covered: Vec<&String> = Vec::new();
+        let mut has_wildcard = false;
+        let mut known = true;
+        for arm in arms {
+            if has_wildcard {
+                self.error(TypeErrorKind::UnreachableArm, span);
+                break;
+            }
+            let (enum_name, variant) = match &arm.pattern {
+                Pattern::Wildcard => {
+                    has_wildcard = true;
+                    continue;
+                }
+                Pattern::Variant { enum_name, variant } => (enum_name, variant),
+            };
+            let variants = match self.enums.get(enum_name) {
+                Some(v) => v.clone(),
+                None => {
+                    self.error(TypeErrorKind::UnknownType(enum_name.clone()), span);
+                    known = false;
+                    continue;
+                }
+            };
+            match &matched {
+                Some(first) if first != enum_name => {
+                    self.error(TypeErrorKind::MixedMatch { first: first.clone(), second: enum_name.clone() }, span);
+                    known = false;
+                    continue;
+                }
+                _ => matched = Some(enum_name.clone()),
+            }
+            if !variants.contains(variant) {
+                self.error(TypeErrorKind::NoField { on: enum_name.clone(), field: variant.clone() }, span);
+                known = false;
+            } else if covered.contains(&variant) {
+                self.error(TypeErrorKind::DuplicateArm(format!("{}.{}", enum_name, variant)), span);
+            } else {
+                covered.push(variant);
+            }
+        }
+        if !known {
+            return None;
+        }
+        let enum_name = match matched {
+            Some(e) => e,
+            None if has_wildcard => return None,
+            None => {
+                self.error(TypeErrorKind::EmptyMatch, span);
+                return None;
+            }
+        };
+        if !has_wildcard {
+            let missing: Vec<String> = self.enums[&enum_name]
+                .iter()
+                .filter(|v| !covered.contains(v))
+                .map(|v| format!("{}.{}", enum_name, v))
+                .collect();
+            if !missing.is_empty() {
+                self.error(TypeErrorKind::NonExhaustive(missing), span);
+            }
+        }
+        return Some(enum_name);
+    }
+
+    // The type of `var name = value;`, any if there is none.
+    fn infer_var(&mut self, name: &String, value: &Option<ExpRef>, span: Span) -> Type {
+        let v = match value {
+            Some(v) => *v,
+            None => return self.error(TypeErrorKind::Untyped(name.clone()), span),
+        };
+        let errors = self.errors.len();
+        let t = self.infer(v);
+        if (t == Type::Any && self.errors.len() == errors) || t == Type::Void {
+            return self.error(TypeErrorKind::CantInferVar { name: name.clone(), found: t }, self.spans[v]);
+        }
+        return t;
+    }
+
+    fn infer(&mut self, exp: ExpRef) -> Type {
+        let t = self.infer_expression(exp);
+        // Instances are compiled from the types of the generic body.
+        if self.bindings.is_empty() {
+            self.types.insert(exp, t.clone());
+        }
+        return t;
+    }
+
+    fn infer_expression(&mut self, exp: ExpRef) -> Type {
+        let span = self.spans[exp];
+        match *self.program[exp].clone() {
+            Expression::Integer(_) => Type::Int,
+            Expression::Char(_) => Type::Char,
+            Expression::String(_) => Type::String,
+            Expression::Identifier { value, decl, .. } => {
+                let t = match self.lookup(&value) {
+                    Some(t) => t.clone(),
+                    None => match self.functions.get(&value) {
+                        Some(f) if !f.type_params.is_empty() => {
+                            self.error(TypeErrorKind::MissingTypeArguments(value.clone()), span)
+                        }
+                        Some(f) if f.external => self.error(TypeErrorKind::ExternValue(value.clone()), span),
+                        Some(f) => Type::function(f.params.clone(), f.return_type.clone()),
+                        None => Type::Any,
+                    },
+                };
+                if self.bindings.is_empty() {
+                    self.program[exp] = Box::new(Expression::Identifier {
+                        value,
+                        ident_type: t.clone(),
+                        decl,
+                    });
+                }
+                t
+            }
+            Expression::InfixExpression { left, op, right } => self.infer_infix(left, op, right, span),
+            Expression::AssignExpression { left, right } => self.infer_infix(left, TokenType::Assign, right, span),
+            Expression::PrefixExpression { op, right } => {
+                let t = self.infer(right);
+                match op {
+                    TokenType::Minus if self.is_numeric(&t) => Type::Int,
+                    _ => self.error(TypeErrorKind::PrefixOperator { op, operand: t }, span),
+                }
+            }
+            Expression::FunctionCall { left, parameters } => self.infer_call(left, &parameters, span),
+            Expression::FieldAccess { left, field } => {
+                if let Expression::Identifier { value, .. } = &*self.program[left] {
+                    if self.lookup(value).is_none() && self.enums.contains_key(value) {
+                        let enum_name = value.clone();
+                        if !self.enums[&enum_name].contains(&field) {
+                            return self.error(TypeErrorKind::NoField { on: enum_name, field }, span);
+                        }
+                        return Type::Named(enum_name);
+                    }
+                }
+                let t = self.infer(left);
+                if t == Type::Any {
+                    return t;
+                }
+                match aggregate_fields(&t, &self.structs).and_then(|f| f.into_iter().find(|(n, _)| *n == field)) {
+                    Some((_, field_type)) => field_type,
+                    None => self.error(TypeErrorKind::NoField { on: t.to_string(), field }, span),
+                }
+            }
+            Expression::Index { left, index } => {
+                self.expect(left, &Type::String);
+                self.expect(index, &Type::Int);
+                Type::Char
+            }
+            Expression::Slice { left, start, end } => {
+                self.expect(left, &Type::String);
+                for e in start.into_iter().chain(end) {
+                    self.expect(e, &Type::Int);
+                }
+                Type::String
+            }
+            Expression::Tuple(elements) => {
+                Type::Tuple(elements.iter().map(|e| self.infer(*e)).collect())
+            }
+            Expression::Lambda { call_inputs, return_type, mut body } => {
+                self.function(&call_inputs, &return_type, &mut body, span);
+                let params = call_inputs.iter().map(|p| self.bind(&p.param_type)).collect();
+                let t = Type::function(params, return_type.as_ref().map(|r| self.bind(r)));
+                // The body now has the inferred types of its variables.
+                if self.bindings.is_empty() {
+                    self.program[exp] = Box::new(Expression::Lambda { call_inputs, return_type, body });
+                }
+                t
+            }
+            Expression::GenericInstance { value, type_args } => {
+                let type_args: Vec<Type> = type_args.iter().map(|t| self.bind(t)).collect();
+                for t in &type_args {
+                    self.check_type(t, span);
+                }
+                let f = match self.functions.get(&value) {
+                    Some(f) if !f.type_params.is_empty() => f,
+                    _ => return self.error(TypeErrorKind::NotCallable(Type::Named(value)), span),
+                };
+                if f.type_params.len() != type_args.len() {
+                    let expected = f.type_params.len();
+                    return self.error(TypeErrorKind::TypeArguments { name: value, expected, found: type_args.len() }, span);
+                }
+                let bindings: HashMap<String, Type> = f.type_params.iter().cloned().zip(type_args.clone()).collect();
+                let params = f.params.iter().map(|p| substitute(p, &bindings)).collect();
+                let t = Type::function(params, f.return_type.as_ref().map(|r| substitute(r, &bindings)));
+                self.instantiate(&value, type_args);
+                t
+            }
+        }
+    }
+
+    fn infer_infix(&mut self, left: ExpRef, op: TokenType, right: ExpRef, span: Span) -> Type {
+        if op == TokenType::Assign {
+            let assignable = match &*self.program[left] {
+                Expression::Identifier { value, .. } => self.lookup(value).is_some(),
+                _ => false,
+            };
+            if !assignable {
+                self.infer(right);
+                self.error(TypeErrorKind::NotAssignable, self.spans[left]);
+                return Type::Void;
+            }
+            let t = self.infer(left);
+            self.expect(right, &t);
+            return Type::Void;
+        }
+        let l = self.infer(left);
+        let r = self.infer(right);
+        // The backend has no division and no logical operators.
+        if matches!(op, TokenType::Slash | TokenType::And | TokenType::Or) {
+            return self.error(TypeErrorKind::Operator { op, left: l, right: r }, span);
+        }
+        let strings = l == Type::String && r == Type::String;
+        if l == Type::Any || r == Type::Any {
+            let string = op == TokenType::Plus && (l == Type::String || r == Type::String);
+            return if string { Type::String } else { Type::Int };
+        }
+        let numeric = self.is_numeric(&l) && self.is_numeric(&r);
+        let result = match op {
+            TokenType::Plus if strings => Some(Type::String),
+            TokenType::Plus | TokenType::Minus | TokenType::Astrik if numeric => {
+                Some(if l == Type::Char || self.is_type_param(&l) { l.clone() } else { Type::Int })
+            }
+            TokenType::LT | TokenType::GT | TokenType::LTEQ | TokenType::GTEQ if numeric => Some(Type::Int),
+            TokenType::EQ | TokenType::NotEQ if numeric || strings => Some(Type::Int),
+            _ => None,
+        };
+        match result {
+            Some(t) => t,
+            None => self.error(TypeErrorKind::Operator { op, left: l, right: r }, span),
+        }
+    }
+
+    fn infer_call(&mut self, left: ExpRef, parameters: &Vec<ExpRef>, span: Span) -> Type {
+        let (name, type_args) = match &*self.program[left] {
+            Expression::Identifier { value, .. } if self.lookup(value).is_none() => (Some(value.clone()), None),
+            Expression::GenericInstance { value, type_args } => (Some(value.clone()), Some(type_args.clone())),
+            _ => (None, None),
+        };
+        if let Some(name) = &name {
+            if let Some(t) = self.infer_builtin(name, parameters, span) {
+                return t;
+            }
+            if self.structs.contains_key(name) {
+                return self.infer_generic_call(name, type_args, parameters, span, true);
+            }
+            if self.functions.get(name).is_some_and(|f| !f.type_params.is_empty()) {
+                return self.infer_generic_call(name, type_args, parameters, span, false);
+            }
+            // The name of an extern function is never a value, calls to it
+            // are checked here.
+            if let Some(f) = self.functions.get(name) {
+                if f.external {
+                    let params = f.params.clone();
+                    let return_type = f.return_type.clone();
+                    let arity = if f.variadic { parameters.len() >= params.len() } else { parameters.len() == params.len() };
+                    if !arity {
+                        return self.error(TypeErrorKind::Arguments { callee: name.clone(), expected: params.len(), found: parameters.len() }, span);
+                    }
+                    for (i, p) in parameters.iter().enumerate() {
+                        match params.get(i) {
+                            Some(t) => self.expect(*p, t),
+                            None => {
+                                let t = self.infer(*p);
+                                if self.slots(&t) != 1 {
+                                    self.error(TypeErrorKind::ExternType { name: name.clone(), found: t }, self.spans[*p]);
+                                }
+                            }
+                        }
+                    }
+                    return return_type.unwrap_or(Type::Void);
+                }
+            }
+        }
+        let callee = name.unwrap_or_default();
+        let t = self.infer(left);
+        if t == Type::Any {
+            for p in parameters {
+                self.infer(*p);
+            }
+            return t;
+        }
+        let (params, return_type) = match &t {
+            Type::Function { params, return_type } => (params.clone(), *return_type.clone()),
+            _ => return self.error(TypeErrorKind::NotCallable(t), span),
+        };
+        if params.len() != parameters.len() {
+            let callee = if callee.is_empty() { t.to_string() } else { callee };
+            return self.error(TypeErrorKind::Arguments { callee, expected: params.len(), found: parameters.len() }, span);
+        }
+        for (p, expected) in parameters.iter().zip(params) {
+            self.expect(*p, &expected);
+        }
+        return return_type;
+    }
+
+    // print, println, len, int and char, unless a function of that name exists.
+    fn infer_builtin(&mut self, name: &String, parameters: &Vec<ExpRef>, span: Span) -> Option<Type> {
+        if self.functions.contains_key(name) {
+            return None;
+        }
+        let (arg, result) = match name.as_str() {
+            "print" | "println" => {
+                if parameters.is_empty() {
+                    return Some(self.error(TypeErrorKind::Arguments { callee: name.clone(), expected: 1, found: 0 }, span));
+                }
+                self.expect(parameters[0], &Type::String);
+                let found: Vec<Type> = parameters[1..].iter().map(|p| self.infer(*p)).collect();
+                if let Expression::String(fmt) = &*self.program[parameters[0]] {
+                    self.check_format(name, &fmt.clone(), parameters, found, span);
+                    return Some(Type::Int);
+                }
+                // Without a literal format only the arguments passed to printf can be checked.
+                for (p, t) in parameters[1..].iter().zip(found) {
+                    if self.slots(&t) != 1 {
+                        self.error(TypeErrorKind::ExternType { name: String::from("printf"), found: t }, self.spans[*p]);
+                    }
+                }
+                return Some(Type::Int);
+            }
+            "len" => (Type::String, Type::Int),
+            "int" => (Type::Int, Type::Int),
+            "char" => (Type::Int, Type::Char),
+            _ => return None,
+        };
+        if parameters.len() != 1 {
+            return Some(self.error(TypeErrorKind::Arguments { callee: name.clone(), expected: 1, found: parameters.len() }, span));
+        }
+        let t = self.infer(parameters[0]);
+        let fits = if arg == Type::Int { self.is_numeric(&t) } else { self.fits(&arg, &t) };
+        if !fits {
+            self.error(TypeErrorKind::Mismatch { expected: arg, found: t }, self.spans[parameters[0]]);
+        }
+        return Some(result);
+    }
+
+    // Only literal format strings can be checked, the arguments follow the
+    // format in `parameters`.
+    fn check_format(&mut self, name: &String, fmt: &str, parameters: &Vec<ExpRef>, found: Vec<Type>, span: Span) {
+        let conversions = match parse_format(fmt) {
+            Ok(c) => c,
+            Err(e) => {
+                self.error(TypeErrorKind::Format(e), self.spans[parameters[0]]);
+                return;
+            }
+        };
+        if conversions.len() != found.len() {
+            self.error(TypeErrorKind::FormatArguments { callee: name.clone(), expected: conversions.len(), found: found.len() }, span);
+            return;
+        }
+        for ((letter, expected), (p, t)) in conversions.into_iter().zip(parameters[1..].iter().zip(found)) {
+            let fits = match expected {
+                Conversion::Int | Conversion::Char => self.is_numeric(&t),
+                Conversion::String => self.fits(&Type::String, &t),
+                Conversion::Float => t == Type::Any,
+            };
+            if !fits {
+                self.error(TypeErrorKind::FormatMismatch { letter, expected, found: t }, self.spans[*p]);
+            }
+        }
+    }
+
+    // Calls of generic functions and struct constructors, type arguments that
+    // aren't given are inferred from the arguments.
+    fn infer_generic_call(&mut self, name: &String, type_args: Option<Vec<Type>>, parameters: &Vec<ExpRef>, span: Span, constructor: bool) -> Type {
+        let (type_params, params, return_type) = if constructor {
+            let s = &self.structs[name];
+            (s.type_params.clone(), s.fields.iter().map(|(_, t)| t.clone()).collect::<Vec<Type>>(), None)
+        } else {
+            let f = &self.functions[name];
+            (f.type_params.clone(), f.params.clone(), f.return_type.clone())
+        };
+        if params.len() != parameters.len() {
+            return self.error(TypeErrorKind::Arguments { callee: name.clone(), expected: params.len(), found: parameters.len() }, span);
+        }
+        let mut bindings = HashMap::new();
+        if let Some(args) = type_args {
+            let args: Vec<Type> = args.iter().map(|t| self.bind(t)).collect();
+            for t in &args {
+                self.check_type(t, span);
+            }
+            if args.len() != type_params.len() {
+                return self.error(TypeErrorKind::TypeArguments { name: name.clone(), expected: type_params.len(), found: args.len() }, span);
+            }
+            bindings = type_params.iter().cloned().zip(args).collect();
+            for (p, expected) in parameters.iter().zip(&params) {
+                self.expect(*p, &substitute(expected, &bindings));
+            }
+        } else {
+            for (p, pattern) in parameters.iter().zip(&params) {
+                let found = self.infer(*p);
+                if !unify(pattern, &found, &type_params, &mut bindings) {
+                    let expected = substitute(pattern, &bindings);
+                    self.error(TypeErrorKind::Mismatch { expected, found }, self.spans[*p]);
+                }
+            }
+            for param in &type_params {
+                if !bindings.contains_key(param) {
+                    return self.error(TypeErrorKind::CantInfer { param: param.clone(), name: name.clone() }, span);
+                }
+            }
+        }
+        if constructor {
+            let args = type_params.iter().map(|p| bindings[p].clone()).collect();
+            return Type::generic(name, args);
+        }
+        self.instantiate(name, type_params.iter().map(|p| bindings[p].clone()).collect());
+        return return_type.map(|r| substitute(&r, &bindings)).unwrap_or(Type::Void);
+    }
+
+    // Remembers an instance of a generic function to check its body with.
+    // Instances inside generic functions are only known once those are.
+    fn instantiate(&mut self, name: &String, type_args: Vec<Type>) {
+        let generic = type_args.iter().any(|t| self.mentions_type_param(t) || self.mentions_any(t));
+        if !generic {
+            self.instances.push((name.clone(), type_args));
+        }
+    }
+
+    fn mentions_type_param(&self, t: &Type) -> bool {
+        let mut found = self.is_type_param(t);
+        t.map(|inner| {
+            found |= self.mentions_type_param(inner);
+            inner.clone()
+        });
+        return found;
+    }
+
+    fn mentions_any(&self, t: &Type) -> bool {
+        let mut found = *t == Type::Any;
+        t.map(|inner| {
+            found |= self.mentions_any(inner);
+            inner.clone()
+        });
+        return found;
+    }
+
+    // Checks the body of every used instance of a generic function with the
+    // type arguments in place, so operators and calls that don't work for
+    // them are reported instead of reaching the compiler.
+    fn check_instances(&mut self, stmts: &mut Vec<&mut Statement>) {
+        let mut checked: HashSet<(String, Vec<Type>)> = HashSet::new();
+        while let Some((name, type_args)) = self.instances.pop() {
+            if !checked.insert((name.clone(), type_args.clone())) {
+                continue;
+            }
+            let function = stmts.iter_mut().find_map(|s| match &mut **s {
+                Statement::FuncStatement { name: n, type_params, call_inputs, return_type, body, span, .. } if *n == name => {
+                    Some((type_params.clone(), call_inputs.clone(), return_type.clone(), body, *span))
+                }
+                _ => None,
+            });
+            // Functions of modules that were not loaded.
+            let (type_params, call_inputs, return_type, body, span) = match function {
+                Some(f) => f,
+                None => continue,
+            };
+            let args: Vec<String> = type_args.iter().map(|t| t.to_string()).collect();
+            let instance = format!("{}<{}>", name, args.join(", "));
+            self.bindings = type_params.into_iter().zip(type_args).collect();
+            let outer = std::mem::take(&mut self.type_params);
+            let errors = self.errors.len();
+            self.function(&call_inputs, &return_type, body, span);
+            let found = self.errors.split_off(errors);
+            self.errors.extend(found.into_iter().map(|e| TypeError {
+                kind: TypeErrorKind::Instance { name: instance.clone(), error: Box::new(e.kind) },
+                span: e.span,
+            }));
+            self.type_params = outer;
+            self.bindings.clear();
+        }
+    }
+}
@@ -15,11 +15,14 @@ pub enum TokenType {
     RBrack,
     
     Assign,
+    Arrow,
 
     Colon,
     Comma,
     Dot,
     Semicolon,
+    Ellipsis,
+    At,
     And,
     Or,
 
@@ -28,9 +31,11 @@ pub enum TokenType {
     GTEQ,
     LTEQ,
     EQ,
+    NotEQ,
 
     Integer,
     String,
+    Char,
     Identifier,
     If,
     Else,
@@ -38,14 +43,29 @@ pub enum TokenType {
     Var,
     Const,
     Return,
+    Enum,
+    Match,
+    Struct,
+    Import,
+    Extern,
+    Pub,
     EOF,
 }
 
 
+// Where a token starts, lines and columns count from 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Span {
+    pub file: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
 #[derive(Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub value: String,
+    pub span: Span,
 }
 
 impl std::fmt::Display for Token {
@@ -2,16 +2,25 @@ use std::collections::HashMap;
 
 use crate::token::*;
 
+#[derive(Clone)]
 pub struct Tokenizer {
     input: String,
     cur_idx: usize,
     cur_char: char,
     next_char: char,
-    keywords: HashMap<String, TokenType>
+    keywords: HashMap<String, TokenType>,
+    file: usize,
+    line: usize,
+    col: usize,
 }
 
 impl Tokenizer {
     pub fn new(s: String) -> Self {
+        return Self::with_file(s, 0);
+    }
+
+    // `file` ends up in the spans of the tokens so errors can name the file.
+    pub fn with_file(s: String, file: usize) -> Self {
         let mut map = HashMap::new();
 
         map.insert(String::from("func"), TokenType::Func);
@@ -20,6 +29,12 @@ impl Tokenizer {
         map.insert(String::from("const"), TokenType::Const);
         map.insert(String::from("if"), TokenType::If);
         map.insert(String::from("else"), TokenType::Else);
+        map.insert(String::from("enum"), TokenType::Enum);
+        map.insert(String::from("match"), TokenType::Match);
+        map.insert(String::from("struct"), TokenType::Struct);
+        map.insert(String::from("import"), TokenType::Import);
+        map.insert(String::from("pub"), TokenType::Pub);
+        map.insert(String::from("extern"), TokenType::Extern);
 
         return Tokenizer {
             input: s.clone(),
@@ -33,11 +48,19 @@ impl Tokenizer {
                 .nth(1)
                 .unwrap(),
             keywords: map,
-
+            file,
+            line: 1,
+            col: 1,
         }
     }
 
     fn shift(&mut self) {
+        if self.cur_char == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
         if self.cur_idx + 2 >= self.input.len() {
             self.cur_char = self.next_char;
             self.next_char = '\0';
@@ -73,17 +96,52 @@ impl Tokenizer {
     fn get_string(&mut self) -> String{
         let mut s = String::new();
         while self.cur_char != '"' {
-            s += &String::from(self.cur_char);
+            if self.cur_char == '\\' {
+                s += &String::from(self.get_escape());
+            } else {
+                s += &String::from(self.cur_char);
+            }
             self.shift();
         }
         return s;
     }
 
+    // Expects the backslash in cur_char and leaves the escaped char in cur_char.
+    fn get_escape(&mut self) -> char {
+        self.shift();
+        match self.cur_char {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '\'' => '\'',
+            '"' => '"',
+            c => panic!("unknown escape sequence \\{}", c),
+        }
+    }
+
+    fn get_char(&mut self) -> String {
+        let c = if self.cur_char == '\\' {
+            self.get_escape()
+        } else {
+            self.cur_char
+        };
+        if !c.is_ascii() {
+            panic!("char literal '{}' does not fit into a byte", c);
+        }
+        self.shift();
+        if self.cur_char != '\'' {
+            panic!("char literal has to contain exactly one char");
+        }
+        return String::from(c);
+    }
+
     fn get_identifier(&mut self) -> String{
         let mut s = String::new();
         while Self::is_number(self.next_char) || 
             Self::is_letter(self.next_char) || 
-            self.cur_char == '_' {
+            self.next_char == '_' {
             s += &String::from(self.cur_char);
             self.shift();
         }
@@ -96,6 +154,11 @@ impl Tokenizer {
         let mut t = Token{
             token_type: TokenType::EOF,
             value: String::from(self.cur_char),
+            span: Span {
+                file: self.file,
+                line: self.line,
+                col: self.col,
+            },
         };
         match self.cur_char {
             '+' => t.token_type = TokenType::Plus,
@@ -111,7 +174,19 @@ impl Tokenizer {
             ':' => t.token_type = TokenType::Colon,
             ';' => t.token_type = TokenType::Semicolon,
             ',' => t.token_type = TokenType::Comma,
-            '.' => t.token_type = TokenType::Dot,
+            '@' => t.token_type = TokenType::At,
+            '.' => {
+                t.token_type = TokenType::Dot;
+                if self.next_char == '.' {
+                    self.shift();
+                    if self.next_char != '.' {
+                        panic!("unknown char");
+                    }
+                    self.shift();
+                    t.token_type = TokenType::Ellipsis;
+                    t.value = String::from("...");
+                }
+            }
             '\0' => t.token_type = TokenType::EOF,
             '=' => {
                 t.token_type = TokenType::Assign;
@@ -119,8 +194,20 @@ impl Tokenizer {
                     t.token_type = TokenType::EQ;
                     t.value = String::from("==");
                     self.shift();
+                } else if self.next_char == '>' {
+                    t.token_type = TokenType::Arrow;
+                    t.value = String::from("=>");
+                    self.shift();
                 }
             }
+            '!' => {
+                if self.next_char != '=' {
+                    panic!("unknown char");
+                }
+                self.shift();
+                t.token_type = TokenType::NotEQ;
+                t.value = String::from("!=");
+            }
             '<' => {
                 t.token_type = TokenType::LT;
                 if self.next_char == '=' {
@@ -161,6 +248,10 @@ impl Tokenizer {
                     self.shift();
                     t.value = self.get_string();
                     t.token_type = TokenType::String;
+                } else if self.cur_char == '\'' {
+                    self.shift();
+                    t.value = self.get_char();
+                    t.token_type = TokenType::Char;
                 } else {
                     t.value = self.get_identifier();
                     match self.keywords.get(&t.value) {